@@ -0,0 +1,99 @@
+//! Command line arguments passed to the game by a launcher, the plugin
+//! doesn't receive its own arguments so the `--pr-*` flags are read from
+//! the command line of the game process. Other arguments belong to the
+//! game and are ignored
+
+use log::{debug, warn};
+use std::sync::OnceLock;
+
+/// Prefix used by all the plugin arguments
+const ARG_PREFIX: &str = "--pr-";
+
+/// Arguments parsed from the game command line
+static LAUNCH_ARGS: OnceLock<LaunchArgs> = OnceLock::new();
+
+/// Plugin arguments provided on the game command line, these take
+/// precedence over the config file
+#[derive(Debug, Default)]
+pub struct LaunchArgs {
+    /// Connection URL to use (--pr-url=<url>)
+    pub connection_url: Option<String>,
+    /// Connect on startup without waiting for the user (--pr-connect)
+    pub connect: bool,
+    /// Connect from the tray without showing the window (--pr-headless)
+    pub headless: bool,
+}
+
+impl LaunchArgs {
+    /// Parses the plugin arguments from the provided command line `args`,
+    /// values can be provided as "--pr-name=value" or "--pr-name value"
+    ///
+    /// ## Arguments
+    /// * `args` - The command line arguments
+    fn parse<I>(args: I) -> Self
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut launch_args = LaunchArgs::default();
+        let mut args = args.into_iter().peekable();
+
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix(ARG_PREFIX) else {
+                continue;
+            };
+
+            let (name, value) = match flag.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (flag, None),
+            };
+
+            match name {
+                "url" => {
+                    // Value may be provided as the next argument
+                    let value =
+                        value.or_else(|| args.next_if(|next| !next.starts_with(ARG_PREFIX)));
+
+                    match value.filter(|value| !value.trim().is_empty()) {
+                        Some(value) => launch_args.connection_url = Some(value),
+                        None => warn!("Missing value for {}url argument", ARG_PREFIX),
+                    }
+                }
+                "connect" => launch_args.connect = true,
+                "headless" => launch_args.headless = true,
+                _ => debug!("Ignoring unknown argument {}", arg),
+            }
+        }
+
+        launch_args
+    }
+}
+
+/// Reads the plugin arguments from the game command line (GetCommandLineW
+/// through [std::env::args_os]), the arguments are applied over the config
+/// by the UI without being written to the config file
+pub fn init() {
+    let args = LAUNCH_ARGS.get_or_init(|| {
+        LaunchArgs::parse(
+            std::env::args_os()
+                // Skip the executable path
+                .skip(1)
+                .map(|arg| arg.to_string_lossy().into_owned()),
+        )
+    });
+
+    if let Some(connection_url) = &args.connection_url {
+        debug!("Using connection URL from command line: {}", connection_url);
+    }
+}
+
+/// Provides the plugin arguments from the game command line, empty
+/// until [init] is called
+pub fn launch_args() -> &'static LaunchArgs {
+    static EMPTY: LaunchArgs = LaunchArgs {
+        connection_url: None,
+        connect: false,
+        headless: false,
+    };
+
+    LAUNCH_ARGS.get().unwrap_or(&EMPTY)
+}
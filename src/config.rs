@@ -1,16 +1,327 @@
-use crate::ui::error_message;
-use log::debug;
+use crate::{game::fingerprint::GameFingerprint, ui::error_message};
+use log::{debug, warn};
 use serde::{Deserialize, Serialize};
-use std::{env::current_exe, path::PathBuf};
+use std::{
+    env::current_exe,
+    ffi::OsString,
+    io::ErrorKind,
+    os::windows::ffi::OsStringExt,
+    path::{Path, PathBuf},
+    ptr::null_mut,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use windows_sys::{
+    core::PWSTR,
+    Win32::{
+        Foundation::S_OK,
+        System::Com::CoTaskMemFree,
+        UI::Shell::{FOLDERID_Documents, SHGetKnownFolderPath, KF_FLAG_DEFAULT},
+    },
+};
 
 /// Name of the file that stores saved pocket relay configuration info
 pub const CONFIG_FILE_NAME: &str = "pocket-relay-client.json";
 
+/// Delay before a queued config write is written to disk
+const CONFIG_WRITE_DELAY: Duration = Duration::from_millis(500);
+
+/// Config queued to be written to disk
+static PENDING_CONFIG: Mutex<Option<ClientConfig>> = Mutex::new(None);
+
 /// Structure of the configuration file
-#[derive(Debug, Deserialize, Serialize)]
+///
+/// Fields missing from the file use the value from [Default], this keeps
+/// config files written by older versions (which may only contain
+/// `connection_url`) loading as new fields are added
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
 pub struct ClientConfig {
     /// The saved connection URL to use
     pub connection_url: String,
+    /// Whether the connection URL should be remembered
+    pub remember: bool,
+    /// Exact game build the hooks should be applied to, overrides the
+    /// default game build detection
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub game_fingerprint: Option<GameFingerprint>,
+    /// Which tunnel the client should use for game traffic
+    pub tunnel_mode: TunnelMode,
+    /// Recently connected servers, most recent first
+    pub recent_servers: Vec<RecentServer>,
+    /// Log the names of game events without handling them
+    pub trace_events: bool,
+    /// IPv4 address to redirect the game to instead of loopback, used
+    /// when the servers are running on another machine. Only the redirector
+    /// is reached through this address, the redirector always directs the
+    /// game to the blaze server on loopback (127.0.0.1)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect_address: Option<String>,
+    /// Start with the window hidden in the system tray, connecting to
+    /// the saved connection URL automatically
+    pub start_minimized: bool,
+    /// Hosts that are allowed to be connected to, when not empty only
+    /// these servers can be used
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed_hosts: Vec<String>,
+    /// Check for plugin updates on startup
+    pub auto_update: bool,
+    /// Log the resolved game offsets after applying the hooks
+    pub dump_offsets: bool,
+    /// Named connection profiles with their own client settings
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub profiles: Vec<ConnectionProfile>,
+    /// Retry HTTPS lookups to loopback servers over HTTP when the
+    /// HTTPS connection fails
+    pub loopback_http_fallback: bool,
+    /// Report lifecycle events to the Windows Application Event Log
+    pub event_log: bool,
+    /// Minimum server version to allow connecting to, can only raise the
+    /// minimum version required by the client
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_server_version: Option<String>,
+    /// Directory containing assets that replace the embedded assets,
+    /// used by white-label builds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource_pack: Option<String>,
+    /// Manual overrides for the game addresses used by the hooks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub advanced: Option<AdvancedConfig>,
+    /// Write trace level logs to a separate log file, produces a large
+    /// amount of output and should only be enabled while diagnosing issues
+    pub trace_log: bool,
+    /// Whether the first run explanation has been shown
+    pub seen_tutorial: bool,
+    /// Hotkey that toggles offline mode while in game (e.g. "Ctrl+Shift+O"),
+    /// offline mode stops redirecting the game to the connected server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offline_hotkey: Option<String>,
+    /// Minutes between checks of the connected server version, notifies
+    /// the player when the server is updated to an incompatible version
+    /// while connected. Disabled when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version_check_interval: Option<u64>,
+    /// Use the client identity file without asking each time it's found
+    pub always_use_identity: bool,
+    /// Write lifecycle events, connection stats and errors as JSON lines
+    /// to a size limited file for external tools
+    pub event_file: bool,
+    /// Keep the game paused after connecting until the local servers are
+    /// listening, prevents the game from connecting before they are ready
+    pub wait_for_servers: bool,
+}
+
+/// Manual overrides for the fixed game addresses used by the hooks,
+/// allows the plugin to be used on modified game builds. Addresses are
+/// written as hex (e.g. "0x01AB5634")
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AdvancedConfig {
+    /// Memory address of the game objects array
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub game_object_offset: Option<String>,
+    /// Memory address of the process event function
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub process_event_offset: Option<String>,
+    /// Address to start searching for the gethostbyname call from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scan_start: Option<String>,
+    /// Address to stop searching for the gethostbyname call at
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scan_end: Option<String>,
+    /// Address of the gethostbyname call, skips searching when set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host_lookup_address: Option<String>,
+}
+
+/// Named connection profile, connecting to the profile connection URL
+/// uses the client identity and proxy of the profile
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ConnectionProfile {
+    /// Name of the profile, can be entered instead of the connection URL
+    pub name: String,
+    /// The connection URL of the server
+    pub connection_url: String,
+    /// Path to a client identity (.p12) to use for this server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity_path: Option<String>,
+    /// URL of an outbound proxy to send requests to this server through
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outbound_proxy: Option<String>,
+    /// Keep the server lookup for the rest of the session instead of
+    /// looking up the server again on every reconnect, the server is
+    /// looked up again after a connection fails
+    #[serde(default)]
+    pub trusted: bool,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            connection_url: String::new(),
+            remember: default_remember(),
+            game_fingerprint: None,
+            tunnel_mode: TunnelMode::default(),
+            recent_servers: Vec::new(),
+            trace_events: false,
+            redirect_address: None,
+            start_minimized: false,
+            allowed_hosts: Vec::new(),
+            auto_update: default_auto_update(),
+            dump_offsets: false,
+            profiles: Vec::new(),
+            loopback_http_fallback: default_loopback_http_fallback(),
+            event_log: false,
+            min_server_version: None,
+            resource_pack: None,
+            advanced: None,
+            trace_log: false,
+            seen_tutorial: false,
+            offline_hotkey: None,
+            version_check_interval: None,
+            always_use_identity: false,
+            event_file: false,
+            wait_for_servers: default_wait_for_servers(),
+        }
+    }
+}
+
+/// Updates are checked for unless disabled
+fn default_auto_update() -> bool {
+    true
+}
+
+/// Local servers are commonly plain HTTP so the fallback is enabled
+fn default_loopback_http_fallback() -> bool {
+    true
+}
+
+/// Waiting for the local servers is only skipped when disabled
+fn default_wait_for_servers() -> bool {
+    true
+}
+
+/// Connection URL is remembered unless disabled, configs from before the
+/// remember setting existed were only saved when it was being remembered
+fn default_remember() -> bool {
+    true
+}
+
+/// Maximum number of recent servers to keep
+const RECENT_SERVERS_LIMIT: usize = 10;
+
+/// Server that was recently connected to
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RecentServer {
+    /// The connection URL of the server
+    pub url: String,
+    /// Unix timestamp in seconds of the last successful connection
+    pub last_connected: u64,
+    /// Server version at the last successful connection
+    pub version: String,
+}
+
+impl ClientConfig {
+    /// Records a successful connection to the server at `url` moving
+    /// it to the front of the recent servers list
+    ///
+    /// ## Arguments
+    /// * `url`     - The connection URL of the server
+    /// * `version` - The version of the server
+    pub fn add_recent_server(&mut self, url: String, version: String) {
+        let last_connected = unix_timestamp();
+
+        self.recent_servers.retain(|server| server.url != url);
+        self.recent_servers.insert(
+            0,
+            RecentServer {
+                url,
+                last_connected,
+                version,
+            },
+        );
+        self.recent_servers.truncate(RECENT_SERVERS_LIMIT);
+    }
+
+    /// Creates a copy of the config that can be shared with other players,
+    /// only the server connection settings are included. Settings specific
+    /// to this machine (paths, hotkeys, logging) use their defaults
+    pub fn to_shareable(&self) -> ClientConfig {
+        ClientConfig {
+            connection_url: self.connection_url.clone(),
+            tunnel_mode: self.tunnel_mode,
+            allowed_hosts: self.allowed_hosts.clone(),
+            loopback_http_fallback: self.loopback_http_fallback,
+            min_server_version: self.min_server_version.clone(),
+            version_check_interval: self.version_check_interval,
+            // Identity paths point to private files on this machine
+            profiles: self
+                .profiles
+                .iter()
+                .map(|profile| ConnectionProfile {
+                    identity_path: None,
+                    ..profile.clone()
+                })
+                .collect(),
+            ..ClientConfig::default()
+        }
+    }
+
+    /// Replaces the server connection settings in this config with the
+    /// settings from an imported `shared` config (See [Self::to_shareable]),
+    /// all other settings are kept
+    ///
+    /// ## Arguments
+    /// * `shared` - The imported config
+    pub fn merge_shared(&mut self, shared: ClientConfig) {
+        let shared = shared.to_shareable();
+
+        self.connection_url = shared.connection_url;
+        self.tunnel_mode = shared.tunnel_mode;
+        self.allowed_hosts = shared.allowed_hosts;
+        self.loopback_http_fallback = shared.loopback_http_fallback;
+        self.min_server_version = shared.min_server_version;
+        self.version_check_interval = shared.version_check_interval;
+        self.profiles = shared.profiles;
+    }
+}
+
+/// Tunnel used for forwarding game traffic
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TunnelMode {
+    /// Use the UDP tunnel when the server provides a tunnel port
+    /// otherwise use the HTTP upgrade tunnel
+    #[default]
+    Auto,
+    /// Prefer the UDP tunnel, falls back to the HTTP upgrade tunnel
+    /// if the server doesn't provide a tunnel port
+    Udp,
+    /// Always use the HTTP upgrade tunnel, for networks that block
+    /// the UDP tunnel port
+    Http,
+}
+
+/// Parses a memory address from the config, addresses are hex with or
+/// without the "0x" prefix
+///
+/// ## Arguments
+/// * `value` - The address to parse
+pub fn parse_address(value: &str) -> Option<usize> {
+    let value = value.trim();
+    let value = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+
+    usize::from_str_radix(value, 16).ok()
+}
+
+/// Provides the current unix timestamp in seconds
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_secs())
+        .unwrap_or_default()
 }
 
 /// Provides a [`PathBuf`] to the configuration file
@@ -22,10 +333,102 @@ pub fn config_path() -> PathBuf {
     parent.join(CONFIG_FILE_NAME)
 }
 
-/// Reads the [`ClientConfig`] from the config file if one is present
+/// Provides a [`PathBuf`] to the fallback configuration file within the
+/// game save directory (Documents/BioWare/Mass Effect 3), used when the
+/// game directory isn't writable (Program Files without admin)
+pub fn fallback_config_path() -> Option<PathBuf> {
+    let documents = documents_path()?;
+    Some(
+        documents
+            .join("BioWare")
+            .join("Mass Effect 3")
+            .join(CONFIG_FILE_NAME),
+    )
+}
+
+/// Runs `action` on the file named `file_name` next to the config file,
+/// when the game directory isn't writable the action is retried on the
+/// file within the fallback config directory. Provides the path that was
+/// used along with the action output
+///
+/// ## Arguments
+/// * `file_name` - Name of the file within the config directory
+/// * `action`    - Action to run on the file path
+pub fn with_fallback_path<T, F>(file_name: &str, mut action: F) -> std::io::Result<(PathBuf, T)>
+where
+    F: FnMut(&Path) -> std::io::Result<T>,
+{
+    let path = config_path().with_file_name(file_name);
+    let err = match action(&path) {
+        Ok(value) => return Ok((path, value)),
+        Err(err) => err,
+    };
+
+    // Only permission errors are recoverable using the fallback path
+    let fallback_path = match fallback_config_path() {
+        Some(value) if err.kind() == ErrorKind::PermissionDenied => value.with_file_name(file_name),
+        _ => return Err(err),
+    };
+
+    warn!(
+        "Game directory isn't writable, using: {}",
+        fallback_path.display()
+    );
+
+    // Ensure the save directory exists
+    if let Some(parent) = fallback_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    action(&fallback_path).map(|value| (fallback_path, value))
+}
+
+/// Provides a [`PathBuf`] to the config file that is in use. Writes go to
+/// the fallback config when the game directory config isn't writable (e.g.
+/// a read-only file) so the fallback config is used when it is the newer
+/// of the two, otherwise the game directory config is used
+pub fn active_config_path() -> PathBuf {
+    let path = config_path();
+    let modified = |path: &Path| path.metadata().and_then(|value| value.modified()).ok();
+
+    let Some((fallback_path, fallback_modified)) = fallback_config_path()
+        .and_then(|fallback_path| modified(&fallback_path).map(|value| (fallback_path, value)))
+    else {
+        return path;
+    };
+
+    match modified(&path) {
+        Some(path_modified) if path_modified >= fallback_modified => path,
+        _ => fallback_path,
+    }
+}
+
+/// Obtains the path to the current users Documents folder
+fn documents_path() -> Option<PathBuf> {
+    let mut path: PWSTR = null_mut();
+
+    let result =
+        unsafe { SHGetKnownFolderPath(&FOLDERID_Documents, KF_FLAG_DEFAULT as u32, 0, &mut path) };
+
+    let documents = if result == S_OK && !path.is_null() {
+        // Find the length of the null terminated path
+        let length = (0..).take_while(|&i| unsafe { *path.add(i) } != 0).count();
+        let value = unsafe { std::slice::from_raw_parts(path, length) };
+        Some(PathBuf::from(OsString::from_wide(value)))
+    } else {
+        None
+    };
+
+    // Path memory must be freed even when the call fails
+    unsafe { CoTaskMemFree(path.cast()) };
+
+    documents
+}
+
+/// Reads the [`ClientConfig`] from the config file if one is present,
+/// the file last written to is read (See [active_config_path])
 pub fn read_config_file() -> Option<ClientConfig> {
-    // Check that the config file exists
-    let file_path = config_path();
+    let file_path = active_config_path();
     if !file_path.exists() {
         return None;
     }
@@ -52,13 +455,12 @@ pub fn read_config_file() -> Option<ClientConfig> {
 }
 
 /// Writes the provided `config` to the config file, this will create a new
-/// file if one is not present
+/// file if one is not present. When the game directory isn't writable the
+/// config is written to the fallback path instead
 ///
 /// ## Arguments
 /// * `config` - The config to write to the file
 pub fn write_config_file(config: ClientConfig) {
-    let file_path = config_path();
-
     // Serialize the config to byte form
     let bytes = match serde_json::to_vec(&config) {
         Ok(value) => value,
@@ -69,7 +471,110 @@ pub fn write_config_file(config: ClientConfig) {
     };
 
     // Write the config bytes to the config file
-    if let Err(err) = std::fs::write(file_path, bytes) {
-        error_message("Failed to save client config", &err.to_string());
+    match with_fallback_path(CONFIG_FILE_NAME, |path| std::fs::write(path, &bytes)) {
+        Ok((path, _)) => debug!("Saved config to: {}", path.display()),
+        Err(err) => error_message("Failed to save client config", &err.to_string()),
+    }
+}
+
+/// Queues the provided `config` to be written to the config file after a
+/// short delay, rapid successive writes are collapsed into a single write
+/// of the most recent config
+///
+/// Must be called from within the tokio runtime
+///
+/// ## Arguments
+/// * `config` - The config to write to the file
+pub fn queue_config_write(config: ClientConfig) {
+    let schedule = match PENDING_CONFIG.lock() {
+        Ok(mut value) => value.replace(config).is_none(),
+        // Lock is poisoned, write the config directly
+        Err(_) => {
+            write_config_file(config);
+            return;
+        }
+    };
+
+    // A flush is already scheduled for the existing pending config
+    if !schedule {
+        return;
+    }
+
+    tokio::spawn(async {
+        tokio::time::sleep(CONFIG_WRITE_DELAY).await;
+        flush_config_write();
+    });
+}
+
+/// Immediately writes any config queued by [queue_config_write]
+pub fn flush_config_write() {
+    let config = match PENDING_CONFIG.lock() {
+        Ok(mut value) => value.take(),
+        Err(_) => return,
+    };
+
+    if let Some(config) = config {
+        write_config_file(config);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ClientConfig;
+
+    /// Config files from older versions only contain the connection URL,
+    /// the remaining fields must use their defaults
+    #[test]
+    fn test_legacy_config() {
+        let config: ClientConfig =
+            serde_json::from_str(r#"{"connection_url":"http://127.0.0.1"}"#).unwrap();
+
+        assert_eq!(config.connection_url, "http://127.0.0.1");
+        assert!(config.remember);
+        assert!(config.auto_update);
+        assert!(config.loopback_http_fallback);
+        assert!(config.wait_for_servers);
+        assert!(config.recent_servers.is_empty());
+        assert!(config.game_fingerprint.is_none());
+    }
+
+    /// Defaults used for a missing config file must match the defaults
+    /// used for fields missing from a config file
+    #[test]
+    fn test_empty_config_matches_default() {
+        let config: ClientConfig = serde_json::from_str("{}").unwrap();
+        let default = ClientConfig::default();
+
+        assert_eq!(
+            serde_json::to_value(config).unwrap(),
+            serde_json::to_value(default).unwrap()
+        );
+    }
+
+    /// Only the server connection settings are shared between machines
+    #[test]
+    fn test_shared_config() {
+        let config = ClientConfig {
+            connection_url: "http://example.com".to_string(),
+            resource_pack: Some("C:\\packs".to_string()),
+            offline_hotkey: Some("Ctrl+O".to_string()),
+            trace_log: true,
+            ..ClientConfig::default()
+        };
+
+        let shared = config.to_shareable();
+        assert_eq!(shared.connection_url, "http://example.com");
+        assert!(shared.resource_pack.is_none());
+        assert!(shared.offline_hotkey.is_none());
+        assert!(!shared.trace_log);
+
+        let mut local = ClientConfig {
+            start_minimized: true,
+            ..ClientConfig::default()
+        };
+        local.merge_shared(config);
+        assert_eq!(local.connection_url, "http://example.com");
+        assert!(local.resource_pack.is_none());
+        assert!(local.start_minimized);
     }
 }
@@ -0,0 +1,119 @@
+use crate::{
+    event_file::{write_event, Event},
+    hooks::process_event::{set_version_warning, set_welcome_message},
+};
+use log::{debug, warn};
+use std::{fmt::Display, sync::Mutex};
+
+/// Current connection state shared by the UI and launcher
+static CONNECTION_STATE: Mutex<ConnectionState> = Mutex::new(ConnectionState::Disconnected);
+
+/// State of the connection to a server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Not connected to any server
+    Disconnected,
+    /// Looking up the server and starting the local servers
+    Connecting,
+    /// Connected and the local servers are running
+    Connected,
+    /// Connected and the game has connected to the local blaze server
+    Active,
+}
+
+impl ConnectionState {
+    /// Checks whether moving from this state to the `next` state is allowed
+    ///
+    /// ## Arguments
+    /// * `next` - The state to move to
+    pub fn can_transition(self, next: ConnectionState) -> bool {
+        use ConnectionState::*;
+
+        matches!(
+            (self, next),
+            // Disconnecting is always allowed
+            (_, Disconnected)
+                | (Disconnected, Connecting)
+                | (Connecting, Connected)
+                | (Connected, Active)
+        )
+    }
+
+    /// Checks whether the local servers are running for a server, the
+    /// game may not have used them yet
+    pub fn is_connected(self) -> bool {
+        matches!(self, ConnectionState::Connected | ConnectionState::Active)
+    }
+}
+
+impl Display for ConnectionState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ConnectionState::Disconnected => "Disconnected",
+            ConnectionState::Connecting => "Connecting",
+            ConnectionState::Connected => "Connected",
+            ConnectionState::Active => "Active",
+        })
+    }
+}
+
+/// Provides the current connection state
+pub fn connection_state() -> ConnectionState {
+    match CONNECTION_STATE.lock() {
+        Ok(value) => *value,
+        Err(err) => *err.into_inner(),
+    }
+}
+
+/// Moves to the `next` connection state, invalid transitions are logged
+/// and rejected. Returns whether the state was changed
+///
+/// ## Arguments
+/// * `next` - The state to move to
+pub fn set_connection_state(next: ConnectionState) -> bool {
+    let mut state = match CONNECTION_STATE.lock() {
+        Ok(value) => value,
+        Err(err) => err.into_inner(),
+    };
+
+    if !state.can_transition(next) {
+        warn!(
+            "Rejected invalid connection state change {} -> {}",
+            *state, next
+        );
+        return false;
+    }
+
+    if *state != next {
+        debug!("Connection state changed {} -> {}", *state, next);
+        *state = next;
+
+        // Server messages are only for the server that was connected to
+        if next == ConnectionState::Disconnected {
+            set_welcome_message(None);
+            set_version_warning(None);
+            write_event(Event::Disconnected);
+        }
+    }
+
+    true
+}
+
+/// Moves from [ConnectionState::Connected] to [ConnectionState::Active] once
+/// the game has an established connection to the local blaze server, other
+/// states are unchanged
+pub fn mark_connection_active() {
+    let mut state = match CONNECTION_STATE.lock() {
+        Ok(value) => value,
+        Err(err) => err.into_inner(),
+    };
+
+    if *state == ConnectionState::Connected {
+        debug!(
+            "Connection state changed {} -> {}",
+            *state,
+            ConnectionState::Active
+        );
+        *state = ConnectionState::Active;
+    }
+}
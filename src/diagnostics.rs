@@ -0,0 +1,299 @@
+use crate::{
+    config::{unix_timestamp, with_fallback_path},
+    core::{
+        api::{create_server_stream, DETAILS_ENDPOINT},
+        reqwest::Client,
+        servers::{has_server_tasks, HTTP_PORT, REDIRECTOR_PORT},
+    },
+    event_file::{write_event, Event},
+    event_log::{report_event, EventKind},
+    game::{core::game_object_offset, fingerprint::read_fingerprint},
+    hooks::{
+        hook_status,
+        host_lookup::hooked_address,
+        process_event::{process_event_offset, trampoline_address},
+        HookState,
+    },
+    servers::{self, ConnectOptions},
+};
+use log::{debug, error};
+use std::{
+    fmt::{Display, Write},
+    net::Ipv4Addr,
+    sync::Mutex,
+    time::Duration,
+};
+use tokio::{net::TcpStream, time::timeout};
+
+/// Name of the file the last error is written to
+pub const LAST_ERROR_FILE_NAME: &str = "pocket-relay-last-error.txt";
+
+/// The most recently recorded error
+static LAST_ERROR: Mutex<Option<LastError>> = Mutex::new(None);
+
+/// Category of a recorded error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// Failed to connect to a server
+    Connection,
+    /// A local server failed while running
+    Server,
+    /// Failed to update the plugin
+    Update,
+}
+
+impl Display for ErrorCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ErrorCategory::Connection => "Connection",
+            ErrorCategory::Server => "Server",
+            ErrorCategory::Update => "Update",
+        })
+    }
+}
+
+/// Error recorded for later viewing
+#[derive(Debug, Clone)]
+pub struct LastError {
+    /// The error message
+    pub message: String,
+    /// Unix timestamp in seconds of when the error occurred
+    pub timestamp: u64,
+    /// Category of the error
+    pub category: ErrorCategory,
+}
+
+/// Records an error as the last error, the error is kept in memory and
+/// written to [LAST_ERROR_FILE_NAME] so it can be viewed after the error
+/// dialog is dismissed
+///
+/// ## Arguments
+/// * `category` - The category of the error
+/// * `message`  - The error message
+pub fn record_error(category: ErrorCategory, message: impl Into<String>) {
+    let last_error = LastError {
+        message: message.into(),
+        timestamp: unix_timestamp(),
+        category,
+    };
+
+    write_last_error_file(&last_error);
+    write_event(Event::Error {
+        category: last_error.category.to_string(),
+        message: &last_error.message,
+    });
+    report_event(
+        EventKind::Error,
+        &format!("{} error: {}", last_error.category, last_error.message),
+    );
+
+    if let Ok(mut value) = LAST_ERROR.lock() {
+        *value = Some(last_error);
+    }
+}
+
+/// Provides the most recently recorded error
+pub fn last_error() -> Option<LastError> {
+    LAST_ERROR.lock().ok()?.clone()
+}
+
+/// Writes the `last_error` to the last error file next to the config file,
+/// the fallback config directory is used when the game directory isn't
+/// writable. Failures are only logged
+///
+/// ## Arguments
+/// * `last_error` - The error to write
+fn write_last_error_file(last_error: &LastError) {
+    let contents = format!(
+        "Category: {}\nTimestamp: {}\nError: {}\n",
+        last_error.category, last_error.timestamp, last_error.message
+    );
+
+    match with_fallback_path(LAST_ERROR_FILE_NAME, |path| std::fs::write(path, &contents)) {
+        Ok((path, _)) => debug!("Saved last error to: {}", path.display()),
+        Err(err) => error!("Failed to save last error: {}", err),
+    }
+}
+
+/// Logs the resolved game offsets and hook addresses in a format that can
+/// be copied into an issue, used when porting the hooks to a new game build.
+/// Should be called after the hooks have been applied
+pub fn dump_offsets() {
+    /// Formats an optional address
+    fn address(value: Option<usize>) -> String {
+        match value {
+            Some(value) => format!("{:#010x}", value),
+            None => "(none)".to_string(),
+        }
+    }
+
+    let (module_base, fingerprint) = match read_fingerprint() {
+        Some((base, fingerprint)) => (Some(base), fingerprint.to_string()),
+        None => (None, "(unknown)".to_string()),
+    };
+
+    let mut out = String::new();
+    let _ = writeln!(out, "--- Pocket Relay offsets ---");
+    let _ = writeln!(out, "fingerprint: {}", fingerprint);
+    let _ = writeln!(out, "module_base: {}", address(module_base));
+    let _ = writeln!(out, "game_objects: {:#010x}", game_object_offset());
+    let _ = writeln!(out, "process_event: {:#010x}", process_event_offset());
+    let _ = writeln!(
+        out,
+        "process_event_trampoline: {}",
+        address(trampoline_address())
+    );
+    let _ = writeln!(out, "host_lookup_thunk: {}", address(hooked_address()));
+    let _ = write!(out, "----------------------------");
+
+    debug!("Resolved offsets:\n{}", out);
+}
+
+/// Maximum time each network check in [run_connection_checks] may take
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Result of a single connection check
+#[derive(Debug)]
+pub enum CheckResult {
+    /// The check passed, contains details about the result
+    Pass(String),
+    /// The check failed, contains the reason
+    Fail(String),
+    /// The check couldn't be run, contains the reason
+    Skipped(&'static str),
+}
+
+/// Connection check with its result
+#[derive(Debug)]
+pub struct ConnectionCheck {
+    /// Name of the check
+    pub name: &'static str,
+    /// Result of the check
+    pub result: CheckResult,
+}
+
+impl Display for ConnectionCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.result {
+            CheckResult::Pass(details) => write!(f, "[PASS] {}: {}", self.name, details),
+            CheckResult::Fail(reason) => write!(f, "[FAIL] {}: {}", self.name, reason),
+            CheckResult::Skipped(reason) => write!(f, "[SKIP] {}: {}", self.name, reason),
+        }
+    }
+}
+
+/// Checks each stage of the connection path in order and provides the
+/// result of every check, used to find which stage is failing when the
+/// game isn't connecting. The local server checks are only run while
+/// connected
+///
+/// ## Arguments
+/// * `http_client` - The HTTP client to use
+/// * `target`      - The connection URL of the server
+/// * `options`     - The options to connect with
+pub async fn run_connection_checks(
+    http_client: Client,
+    target: String,
+    options: ConnectOptions,
+) -> Vec<ConnectionCheck> {
+    let mut checks = Vec::new();
+
+    // Without the hook the game never reaches the local servers
+    let host_lookup = hook_status().host_lookup;
+    checks.push(ConnectionCheck {
+        name: "Host lookup hook",
+        result: match host_lookup {
+            HookState::Applied => CheckResult::Pass("applied".to_string()),
+            HookState::Failed(reason) => CheckResult::Fail(reason.to_string()),
+            HookState::NotApplied => {
+                CheckResult::Fail("not applied (unsupported game version)".to_string())
+            }
+        },
+    });
+
+    let lookup = timeout(
+        CHECK_TIMEOUT,
+        servers::lookup_target(&servers::lookup_client(&http_client), &target, &options),
+    )
+    .await;
+
+    let (lookup, result) = match lookup {
+        Ok(Ok(lookup)) => {
+            let details = format!("{} (v{})", lookup.url, lookup.version);
+            (Some(lookup), CheckResult::Pass(details))
+        }
+        Ok(Err(err)) => (None, CheckResult::Fail(err.to_string())),
+        Err(_) => (None, CheckResult::Fail("timed out".to_string())),
+    };
+
+    checks.push(ConnectionCheck {
+        name: "Server lookup",
+        result,
+    });
+
+    checks.push(ConnectionCheck {
+        name: "Blaze connection upgrade",
+        result: match &lookup {
+            Some(lookup) => {
+                let stream = timeout(
+                    CHECK_TIMEOUT,
+                    create_server_stream(&http_client, &lookup.url, lookup.association.as_ref()),
+                )
+                .await;
+
+                // The upgraded stream is dropped without sending anything
+                match stream {
+                    Ok(Ok(_)) => CheckResult::Pass("upgraded".to_string()),
+                    Ok(Err(err)) => CheckResult::Fail(err.to_string()),
+                    Err(_) => CheckResult::Fail("timed out".to_string()),
+                }
+            }
+            None => CheckResult::Skipped("server lookup failed"),
+        },
+    });
+
+    let connected = has_server_tasks();
+
+    checks.push(ConnectionCheck {
+        name: "Local redirector",
+        result: if connected {
+            match timeout(
+                CHECK_TIMEOUT,
+                TcpStream::connect((Ipv4Addr::LOCALHOST, REDIRECTOR_PORT)),
+            )
+            .await
+            {
+                Ok(Ok(_)) => CheckResult::Pass(format!("listening on port {}", REDIRECTOR_PORT)),
+                Ok(Err(err)) => CheckResult::Fail(err.to_string()),
+                Err(_) => CheckResult::Fail("timed out".to_string()),
+            }
+        } else {
+            CheckResult::Skipped("not connected")
+        },
+    });
+
+    checks.push(ConnectionCheck {
+        name: "Local HTTP proxy",
+        result: if connected {
+            // Requested through the proxy which forwards to the server
+            let url = format!("http://127.0.0.1:{}/{}", HTTP_PORT, DETAILS_ENDPOINT);
+            match http_client.get(url).timeout(CHECK_TIMEOUT).send().await {
+                Ok(response) if response.status().is_success() => {
+                    CheckResult::Pass("reached the server".to_string())
+                }
+                Ok(response) => {
+                    CheckResult::Fail(format!("server responded with {}", response.status()))
+                }
+                Err(err) => CheckResult::Fail(err.to_string()),
+            }
+        } else {
+            CheckResult::Skipped("not connected")
+        },
+    });
+
+    for check in &checks {
+        debug!("Connection check {}", check);
+    }
+
+    checks
+}
@@ -0,0 +1,156 @@
+//! Optional JSON lines event file for external tools, each line is a
+//! self-contained JSON object with a timestamp and event type so the
+//! file can be tailed without parsing the human readable logs
+
+use crate::config::{unix_timestamp, with_fallback_path};
+use log::{debug, warn};
+use serde::Serialize;
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+};
+
+/// Name of the event file
+pub const EVENT_FILE_NAME: &str = "pocket-relay-events.jsonl";
+
+/// Maximum size of the event file in bytes, the file is moved to
+/// [ROTATED_EVENT_FILE_NAME] when it reaches this size
+const MAX_EVENT_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Name the full event file is moved to, only one previous file is kept
+const ROTATED_EVENT_FILE_NAME: &str = "pocket-relay-events.1.jsonl";
+
+/// Event file, only set when the event file is enabled
+static EVENT_FILE: OnceLock<Mutex<EventFile>> = OnceLock::new();
+
+/// Open event file and its current size
+struct EventFile {
+    /// Path to the event file
+    path: PathBuf,
+    /// The open file
+    file: File,
+    /// Current size of the file in bytes
+    size: u64,
+}
+
+/// Event written to the event file
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// The plugin was loaded
+    Loaded { version: &'a str },
+    /// Connected to a server
+    Connected { url: &'a str, version: String },
+    /// Disconnected from a server
+    Disconnected,
+    /// Stats for a connection that is ending
+    ConnectionStats {
+        redirects: u32,
+        latency_ms: Option<u32>,
+    },
+    /// An error was recorded
+    Error { category: String, message: &'a str },
+    /// The plugin was updated
+    Updated { from: &'a str, to: &'a str },
+}
+
+/// Line written to the event file
+#[derive(Serialize)]
+struct EventLine<'a> {
+    /// Unix timestamp in seconds of the event
+    timestamp: u64,
+    /// The event
+    #[serde(flatten)]
+    event: Event<'a>,
+}
+
+/// Enables writing events to [EVENT_FILE_NAME] next to the config file,
+/// the fallback config directory is used when the game directory isn't
+/// writable. Events from previous runs are kept
+pub fn init() {
+    let Some((path, file)) = open_event_file() else {
+        return;
+    };
+
+    let size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+
+    debug!("Writing events to: {}", path.display());
+
+    let _ = EVENT_FILE.set(Mutex::new(EventFile { path, file, size }));
+}
+
+/// Opens the event file for appending, provides the path and file
+/// if it could be opened
+fn open_event_file() -> Option<(PathBuf, File)> {
+    let result = with_fallback_path(EVENT_FILE_NAME, |path| {
+        OpenOptions::new().create(true).append(true).open(path)
+    });
+
+    match result {
+        Ok(value) => Some(value),
+        Err(err) => {
+            warn!("Failed to open event file: {}", err);
+            None
+        }
+    }
+}
+
+/// Writes an event to the event file if the event file is enabled
+///
+/// ## Arguments
+/// * `event` - The event to write
+pub fn write_event(event: Event<'_>) {
+    let Some(event_file) = EVENT_FILE.get() else {
+        return;
+    };
+
+    let Ok(mut event_file) = event_file.lock() else {
+        return;
+    };
+
+    let mut line = match serde_json::to_vec(&EventLine {
+        timestamp: unix_timestamp(),
+        event,
+    }) {
+        Ok(value) => value,
+        Err(err) => {
+            warn!("Failed to serialize event: {}", err);
+            return;
+        }
+    };
+    line.push(b'\n');
+
+    if event_file.size + line.len() as u64 > MAX_EVENT_FILE_SIZE {
+        event_file.rotate();
+    }
+
+    match event_file.file.write_all(&line) {
+        Ok(_) => event_file.size += line.len() as u64,
+        Err(err) => warn!("Failed to write event: {}", err),
+    }
+}
+
+impl EventFile {
+    /// Moves the current file to [ROTATED_EVENT_FILE_NAME] replacing any
+    /// previous file and starts a new empty file
+    fn rotate(&mut self) {
+        let rotated_path = self.path.with_file_name(ROTATED_EVENT_FILE_NAME);
+
+        // Rename fails on Windows when the target already exists
+        let _ = std::fs::remove_file(&rotated_path);
+        if let Err(err) = std::fs::rename(&self.path, &rotated_path) {
+            warn!("Failed to rotate event file: {}", err);
+        }
+
+        // The old file is truncated if the rename failed to keep the size bounded
+        match File::create(&self.path) {
+            Ok(file) => {
+                self.file = file;
+                self.size = 0;
+            }
+            Err(err) => warn!("Failed to create new event file: {}", err),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Optional reporting of plugin lifecycle events to the Windows
+//! Application Event Log for centrally monitoring many machines
+
+use log::debug;
+use std::{
+    ffi::OsStr,
+    os::windows::ffi::OsStrExt,
+    ptr::{null, null_mut},
+    sync::atomic::{AtomicIsize, Ordering},
+};
+use windows_sys::Win32::System::EventLog::{
+    RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE,
+    EVENTLOG_WARNING_TYPE,
+};
+
+/// Name of the event source events are reported under
+const EVENT_SOURCE_NAME: &str = "Pocket Relay Client";
+
+/// Handle to the registered event source, zero when event
+/// logging is disabled or the source couldn't be registered
+static EVENT_SOURCE: AtomicIsize = AtomicIsize::new(0);
+
+/// Type of event being reported
+#[derive(Debug, Clone, Copy)]
+pub enum EventKind {
+    /// Normal lifecycle event
+    Information,
+    /// Something went wrong but the plugin can continue
+    Warning,
+    /// An operation failed
+    Error,
+}
+
+/// Converts the provided `value` into a null terminated wide string
+fn to_wide(value: &str) -> Vec<u16> {
+    OsStr::new(value)
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect()
+}
+
+/// Registers the event source enabling event reporting, failures
+/// are ignored and leave event reporting disabled
+pub fn init() {
+    let name = to_wide(EVENT_SOURCE_NAME);
+    let handle = unsafe { RegisterEventSourceW(null(), name.as_ptr()) };
+    if handle == 0 {
+        debug!("Failed to register event log source, event logging disabled");
+        return;
+    }
+
+    EVENT_SOURCE.store(handle, Ordering::Release);
+}
+
+/// Reports an event to the event log if event logging is enabled
+///
+/// ## Arguments
+/// * `kind`    - The type of event
+/// * `message` - The event message
+pub fn report_event(kind: EventKind, message: &str) {
+    let handle = EVENT_SOURCE.load(Ordering::Acquire);
+    if handle == 0 {
+        return;
+    }
+
+    let ty = match kind {
+        EventKind::Information => EVENTLOG_INFORMATION_TYPE,
+        EventKind::Warning => EVENTLOG_WARNING_TYPE,
+        EventKind::Error => EVENTLOG_ERROR_TYPE,
+    };
+
+    let message = to_wide(message);
+    let strings = [message.as_ptr()];
+
+    // Failing to report the event is ignored
+    unsafe {
+        ReportEventW(
+            handle,
+            ty,
+            0,
+            0,
+            null_mut(),
+            strings.len() as u16,
+            0,
+            strings.as_ptr(),
+            null(),
+        )
+    };
+}
@@ -1,28 +1,65 @@
+use crate::hooks::process_event::process_event;
+use log::{error, warn};
 use std::{
     char::decode_utf16,
+    collections::BTreeMap,
     ffi::CStr,
     fmt::{Debug, Display},
     marker::PhantomData,
     mem::ManuallyDrop,
     os::raw::{c_char, c_int, c_uchar, c_uint, c_ulong, c_ushort, c_void},
     str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
 };
 
-/// Static memory address for the game objects
-static GAME_OBJECT_OFFSET: u32 = 0x01AB5634;
+/// Default static memory address for the game objects
+pub const DEFAULT_GAME_OBJECT_OFFSET: usize = 0x01AB5634;
+
+/// Memory address for the game objects, replaced when the
+/// address is manually overridden
+static GAME_OBJECT_OFFSET: AtomicUsize = AtomicUsize::new(DEFAULT_GAME_OBJECT_OFFSET);
+
+/// Provides the memory address for the game objects
+pub fn game_object_offset() -> usize {
+    GAME_OBJECT_OFFSET.load(Ordering::Acquire)
+}
+
+/// Overrides the memory address for the game objects, used for game
+/// builds where the objects are at a different address
+///
+/// ## Arguments
+/// * `offset` - The memory address of the game objects
+pub fn set_game_object_offset(offset: usize) {
+    GAME_OBJECT_OFFSET.store(offset, Ordering::Release);
+}
 
 type GameObjectsArray = TArray<*mut UObject>;
 
-/// Obtains a mutable reference to the global [TArray] of objects
+/// Whether the null game objects pointer has been logged, the objects are
+/// accessed for every game event so the error is only logged once
+static LOGGED_NULL_OBJECTS: AtomicBool = AtomicBool::new(false);
+
+/// Obtains a mutable reference to the global [TArray] of objects, returns
+/// [None] if the array or its data pointer is null (Unexpected game build)
 ///
 /// ## Safety
 ///
 /// In a valid game executable this memory address should always
 /// point to a valid [TArray] of pointers to [UObject]s
-pub unsafe fn game_objects_mut() -> &'static mut TArray<*mut UObject> {
-    (GAME_OBJECT_OFFSET as *const GameObjectsArray as *mut GameObjectsArray)
+pub unsafe fn game_objects_mut() -> Option<&'static mut TArray<*mut UObject>> {
+    let objects = (game_object_offset() as *const GameObjectsArray as *mut GameObjectsArray)
         .as_mut()
-        .expect("Game objects pointer was null")
+        .filter(|objects| !objects.data.is_null());
+
+    if objects.is_none() && !LOGGED_NULL_OBJECTS.swap(true, Ordering::Relaxed) {
+        error!("Game objects pointer was null");
+    }
+
+    objects
 }
 
 /// Gets a function object by its index in the game objects array
@@ -32,11 +69,167 @@ pub unsafe fn game_objects_mut() -> &'static mut TArray<*mut UObject> {
 /// As long as the game is valid and the index provided points to
 /// a [UFunction] object this operation is safe
 pub unsafe fn get_function_object(index: usize) -> Option<*mut UFunction> {
-    let fn_object = *game_objects_mut().get(index)?;
-    let fn_ptr = fn_object.cast::<UFunction>() as *mut _;
+    let fn_object = *game_objects_mut()?.get(index)?;
+    if fn_object.is_null() {
+        return None;
+    }
+
+    let fn_ptr = fn_object.cast::<UFunction>();
     Some(fn_ptr)
 }
 
+/// Cache of function object addresses found by their full name
+static FUNCTION_CACHE: Mutex<BTreeMap<String, usize>> = Mutex::new(BTreeMap::new());
+
+/// Finds a function object by its full name (e.g "Function SFXGame.X.Y"),
+/// found functions are cached so only the first lookup searches the
+/// game objects array
+///
+/// ## Safety
+///
+/// The game objects array must be valid, see [game_objects_mut]
+///
+/// ## Arguments
+/// * `full_name` - The full name of the function
+pub unsafe fn find_function_by_name(full_name: &str) -> Option<*mut UFunction> {
+    if let Some(address) = FUNCTION_CACHE
+        .lock()
+        .ok()
+        .and_then(|cache| cache.get(full_name).copied())
+    {
+        return Some(address as *mut UFunction);
+    }
+
+    let fn_ptr = game_objects_mut()?
+        .iter()
+        .copied()
+        .find(|object| {
+            object
+                .as_ref()
+                .is_some_and(|object| object.get_full_name() == full_name)
+        })?
+        .cast::<UFunction>();
+
+    if let Ok(mut cache) = FUNCTION_CACHE.lock() {
+        cache.insert(full_name.to_string(), fn_ptr as usize);
+    }
+
+    Some(fn_ptr)
+}
+
+/// Cache of game object array indexes found by their full name
+static OBJECT_INDEX_CACHE: Mutex<BTreeMap<String, usize>> = Mutex::new(BTreeMap::new());
+
+/// Time a missing object was last warned about by its full name
+static MISSING_OBJECT_WARNINGS: Mutex<BTreeMap<String, Instant>> = Mutex::new(BTreeMap::new());
+
+/// Minimum time between warnings about the same missing object
+const MISSING_OBJECT_WARNING_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Finds an object by its full name (e.g "SFXOnlineComponentUI X.Y"), the
+/// index of found objects is cached and checked against the name before
+/// being reused as objects can be replaced while the game is running.
+/// Missing objects are warned about at most once every
+/// [MISSING_OBJECT_WARNING_INTERVAL]
+///
+/// ## Safety
+///
+/// The game objects array must be valid, see [game_objects_mut]
+///
+/// ## Arguments
+/// * `full_name` - The full name of the object
+pub unsafe fn find_object_by_full_name(full_name: &str) -> Option<*mut UObject> {
+    let objects = game_objects_mut()?;
+    let matches = |object: *mut UObject| {
+        object
+            .as_ref()
+            .is_some_and(|object| object.get_full_name() == full_name)
+    };
+
+    let cached_index = OBJECT_INDEX_CACHE
+        .lock()
+        .ok()
+        .and_then(|cache| cache.get(full_name).copied());
+
+    if let Some(object) = cached_index
+        .and_then(|index| objects.get(index).copied())
+        .filter(|object| matches(*object))
+    {
+        return Some(object);
+    }
+
+    let Some((index, object)) = objects
+        .iter()
+        .copied()
+        .enumerate()
+        .find(|(_, object)| matches(*object))
+    else {
+        warn_missing_object(full_name);
+        return None;
+    };
+
+    if let Ok(mut cache) = OBJECT_INDEX_CACHE.lock() {
+        cache.insert(full_name.to_string(), index);
+    }
+
+    Some(object)
+}
+
+/// Warns that the object with the provided full name could not be found,
+/// repeated warnings for the same object are suppressed
+///
+/// ## Arguments
+/// * `full_name` - The full name of the missing object
+fn warn_missing_object(full_name: &str) {
+    let Ok(mut warnings) = MISSING_OBJECT_WARNINGS.lock() else {
+        return;
+    };
+
+    let now = Instant::now();
+    if warnings
+        .get(full_name)
+        .is_some_and(|last| now.duration_since(*last) < MISSING_OBJECT_WARNING_INTERVAL)
+    {
+        return;
+    }
+
+    warnings.insert(full_name.to_string(), now);
+    warn!("Missing game object {}", full_name);
+}
+
+/// Calls the function with the provided full name on `object` using
+/// process event, returns whether the function was found and called
+///
+/// ## Safety
+///
+/// `object` must be a valid object of the class that declares the function
+/// and `params` must match the layout of the function parameters (Including
+/// space for the return value and any out parameters)
+///
+/// ## Arguments
+/// * `object`    - The object to call the function on
+/// * `full_name` - The full name of the function (e.g "Function SFXGame.X.Y")
+/// * `params`    - The function parameters
+pub unsafe fn call_function_by_name<P>(
+    object: *mut UObject,
+    full_name: &str,
+    params: &mut P,
+) -> bool {
+    let Some(fn_ptr) = find_function_by_name(full_name) else {
+        error!("Missing function object {}", full_name);
+        return false;
+    };
+
+    process_event(
+        object,
+        fn_ptr,
+        params as *mut P as *mut c_void,
+        std::ptr::null_mut(),
+    );
+
+    true
+}
+
 /// Trait implemented by things that extend the base
 /// [UObject] C++ class to allow accessing the base object
 pub trait UObjectExt {
@@ -0,0 +1,244 @@
+//! Detection of the running game executable build, the hooks rely on
+//! fixed memory offsets so they must only be applied to a supported build
+
+use crate::{game::core::game_object_offset, hooks::process_event::process_event_offset};
+use serde::{Deserialize, Serialize};
+use std::{
+    env::current_exe,
+    ffi::c_void,
+    fmt::Display,
+    iter::once,
+    ops::Range,
+    os::windows::ffi::OsStrExt,
+    path::Path,
+    ptr::{null, null_mut},
+};
+use windows_sys::{
+    w,
+    Win32::{
+        Storage::FileSystem::{
+            GetFileVersionInfoSizeW, GetFileVersionInfoW, VerQueryValueW, VS_FIXEDFILEINFO,
+        },
+        System::{
+            Diagnostics::Debug::IMAGE_NT_HEADERS32, LibraryLoader::GetModuleHandleW,
+            SystemServices::IMAGE_DOS_HEADER,
+        },
+    },
+};
+
+/// File name of the supported game executable
+const GAME_EXE_NAME: &str = "MassEffect3.exe";
+/// File version of the supported game build (Mass Effect 3 v1.05)
+const GAME_VERSION: FileVersion = FileVersion([1, 5, 5427, 124]);
+/// Magic value at the start of the DOS header ("MZ")
+const DOS_SIGNATURE: u16 = 0x5A4D;
+/// Magic value at the start of the NT headers ("PE\0\0")
+const NT_SIGNATURE: u32 = 0x00004550;
+
+/// Fingerprint identifying a specific build of the game executable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GameFingerprint {
+    /// Link timestamp from the executable file header
+    pub timestamp: u32,
+    /// Size of the loaded executable image
+    pub image_size: u32,
+}
+
+impl Display for GameFingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "timestamp {:#010x} image size {:#010x}",
+            self.timestamp, self.image_size
+        )
+    }
+}
+
+/// Version from the version resource of an executable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileVersion([u16; 4]);
+
+impl Display for FileVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [major, minor, build, revision] = self.0;
+        write!(f, "{major}.{minor}.{build}.{revision}")
+    }
+}
+
+/// Reasons the running game build is considered unsupported
+#[derive(Debug)]
+pub enum UnsupportedGame {
+    /// The executable isn't the game executable
+    UnknownExecutable(String),
+    /// The executable headers couldn't be read
+    InvalidHeaders,
+    /// The executable isn't the supported game version, [None] when the
+    /// version couldn't be read
+    UnsupportedVersion(Option<FileVersion>),
+    /// The executable image doesn't contain the memory offsets used by the hooks
+    OffsetsOutOfRange(GameFingerprint),
+    /// The executable didn't match the expected fingerprint
+    FingerprintMismatch {
+        /// The fingerprint that was expected
+        expected: GameFingerprint,
+        /// The fingerprint of the running executable
+        actual: GameFingerprint,
+    },
+}
+
+impl Display for UnsupportedGame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnsupportedGame::UnknownExecutable(name) => {
+                write!(f, "Plugin was loaded by {name} expected {GAME_EXE_NAME}")
+            }
+            UnsupportedGame::InvalidHeaders => {
+                f.write_str("Failed to read the game executable headers")
+            }
+            UnsupportedGame::UnsupportedVersion(Some(version)) => write!(
+                f,
+                "Game version {version} is not supported, expected version {GAME_VERSION}"
+            ),
+            UnsupportedGame::UnsupportedVersion(None) => write!(
+                f,
+                "Failed to read the game version, expected version {GAME_VERSION}"
+            ),
+            UnsupportedGame::OffsetsOutOfRange(actual) => write!(
+                f,
+                "Game executable ({actual}) doesn't contain the memory offsets used by this plugin"
+            ),
+            UnsupportedGame::FingerprintMismatch { expected, actual } => write!(
+                f,
+                "Game executable ({actual}) doesn't match the expected build ({expected})"
+            ),
+        }
+    }
+}
+
+/// Reads the fingerprint and image base of the game executable from
+/// the headers of the loaded module
+pub fn read_fingerprint() -> Option<(usize, GameFingerprint)> {
+    let base = unsafe { GetModuleHandleW(null()) };
+    if base == 0 {
+        return None;
+    }
+
+    let base = base as usize;
+
+    unsafe {
+        let dos_header = (base as *const IMAGE_DOS_HEADER).read_unaligned();
+        if dos_header.e_magic != DOS_SIGNATURE {
+            return None;
+        }
+
+        let nt_headers =
+            ((base + dos_header.e_lfanew as usize) as *const IMAGE_NT_HEADERS32).read_unaligned();
+        if nt_headers.Signature != NT_SIGNATURE {
+            return None;
+        }
+
+        Some((
+            base,
+            GameFingerprint {
+                timestamp: nt_headers.FileHeader.TimeDateStamp,
+                image_size: nt_headers.OptionalHeader.SizeOfImage,
+            },
+        ))
+    }
+}
+
+/// Provides the address range of the loaded game executable image, [None]
+/// if the image headers couldn't be read
+pub fn image_range() -> Option<Range<usize>> {
+    let (base, fingerprint) = read_fingerprint()?;
+    Some(base..base + fingerprint.image_size as usize)
+}
+
+/// Reads the file version from the version resource of the executable
+/// at `path`
+///
+/// ## Arguments
+/// * `path` - Path to the executable
+fn read_file_version(path: &Path) -> Option<FileVersion> {
+    let path: Vec<u16> = path.as_os_str().encode_wide().chain(once(0)).collect();
+
+    let size = unsafe { GetFileVersionInfoSizeW(path.as_ptr(), null_mut()) };
+    if size == 0 {
+        return None;
+    }
+
+    let mut data = vec![0u8; size as usize];
+    if unsafe { GetFileVersionInfoW(path.as_ptr(), 0, size, data.as_mut_ptr().cast()) } == 0 {
+        return None;
+    }
+
+    let mut info: *mut c_void = null_mut();
+    let mut info_len = 0;
+    if unsafe { VerQueryValueW(data.as_ptr().cast(), w!("\\"), &mut info, &mut info_len) } == 0
+        || info.is_null()
+        || (info_len as usize) < std::mem::size_of::<VS_FIXEDFILEINFO>()
+    {
+        return None;
+    }
+
+    let info = unsafe { (info as *const VS_FIXEDFILEINFO).read_unaligned() };
+
+    Some(FileVersion([
+        (info.dwFileVersionMS >> 16) as u16,
+        info.dwFileVersionMS as u16,
+        (info.dwFileVersionLS >> 16) as u16,
+        info.dwFileVersionLS as u16,
+    ]))
+}
+
+/// Checks that the running executable is a game build the hooks can
+/// be applied to. When an `expected` fingerprint is provided it must
+/// match exactly, otherwise the executable must be the supported version
+/// of the game executable ([GAME_VERSION]) and contain all the fixed
+/// memory offsets used by the hooks
+///
+/// ## Arguments
+/// * `expected` - Optional exact fingerprint the executable must match
+pub fn check_game_build(
+    expected: Option<GameFingerprint>,
+) -> Result<GameFingerprint, UnsupportedGame> {
+    let (base, actual) = read_fingerprint().ok_or(UnsupportedGame::InvalidHeaders)?;
+
+    // Exact fingerprint overrides the default checks
+    if let Some(expected) = expected {
+        if expected != actual {
+            return Err(UnsupportedGame::FingerprintMismatch { expected, actual });
+        }
+
+        return Ok(actual);
+    }
+
+    let exe_path = current_exe().ok();
+    let exe_name = exe_path
+        .as_ref()
+        .and_then(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+        })
+        .unwrap_or_default();
+
+    if !exe_name.eq_ignore_ascii_case(GAME_EXE_NAME) {
+        return Err(UnsupportedGame::UnknownExecutable(exe_name));
+    }
+
+    // Built in check for the supported game build
+    let version = exe_path.as_deref().and_then(read_file_version);
+    if version != Some(GAME_VERSION) {
+        return Err(UnsupportedGame::UnsupportedVersion(version));
+    }
+
+    // All the fixed offsets must be within the loaded image
+    let image = base..base + actual.image_size as usize;
+    let offsets = [game_object_offset(), process_event_offset()];
+
+    if !offsets.iter().all(|offset| image.contains(offset)) {
+        return Err(UnsupportedGame::OffsetsOutOfRange(actual));
+    }
+
+    Ok(actual)
+}
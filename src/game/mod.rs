@@ -1,3 +1,4 @@
 pub mod core;
+pub mod fingerprint;
 pub mod sfxgame;
 pub mod sfxonlinefoundation;
@@ -12,11 +12,13 @@ use std::{
 
 macro_rules! define_method {
     ($func_name:ident, $fn_index:expr, $( $arg_name:ident : $arg_type:ty ),*) => {
+        /// Calls the function returning whether the function was called,
+        /// the call is skipped if the function object couldn't be found
         #[allow(clippy::missing_safety_doc)]
         pub unsafe fn $func_name(
             &mut self,
             $( $arg_name: $arg_type ),*
-        ) {
+        ) -> bool {
             /// Generated structure to hold the function params
             #[repr(C)]
             #[allow(non_camel_case_types)]
@@ -31,7 +33,9 @@ macro_rules! define_method {
                 if let Some(fn_ptr) = get_function_object($fn_index) {
                     FN_PTR = fn_ptr;
                 } else {
-                    panic!("Missing {} ({}) function object", stringify!($func_name), stringify!($fn_index))
+                    // Skip the call rather than crashing the game
+                    log::error!("Missing {} ({}) function object", stringify!($func_name), stringify!($fn_index));
+                    return false;
                 }
             }
 
@@ -46,6 +50,8 @@ macro_rules! define_method {
                 &mut params as *const _ as *mut _,
                 std::ptr::null_mut(),
             );
+
+            true
         }
     };
 }
@@ -1,21 +1,27 @@
 use crate::{
+    config::unix_timestamp,
     core::servers::has_server_tasks,
-    hooks::mem::{find_pattern, use_memory},
+    hooks::mem::{find_unique_pattern, use_memory, PatternError},
 };
-use log::{debug, warn};
+use log::{debug, error, warn};
 use std::{
     ffi::CStr,
+    net::Ipv4Addr,
+    panic::catch_unwind,
     ptr::{addr_of_mut, null_mut},
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
 };
 use windows_sys::{
     core::PCSTR,
     Win32::Networking::WinSock::{gethostbyname, HOSTENT},
 };
 
-/// Address to start matching from
+/// Default address to start matching from
 const HOST_LOOKUP_START_OFFSET: usize = 0x401000;
-/// Address to end matching at
+/// Default address to end matching at
 const HOST_LOOKUP_END_OFFSET: usize = 0xFFFFFF;
+/// Call opcode the gethostbyname call starts with
+const CALL: u8 = 0xE8 /* call */;
 /// Mask to use while matching the opcodes below
 const HOST_LOOKUP_MASK: &str = "x????xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx";
 /// Op codes to match against
@@ -39,9 +45,38 @@ const HOST_LOOKUP_OP_CODES: &[u8] = &[
     0xC7, 0x06, 0x01, 0x00, 0x00, 0x00, // mov dword ptr ds:[esi],1
 ];
 
+/// Address to start matching from, replaced when manually overridden
+static SCAN_START: AtomicUsize = AtomicUsize::new(HOST_LOOKUP_START_OFFSET);
+/// Address to end matching at, replaced when manually overridden
+static SCAN_END: AtomicUsize = AtomicUsize::new(HOST_LOOKUP_END_OFFSET);
+/// Manually provided address of the gethostbyname call, zero when
+/// the call should be found by matching
+static CALL_ADDRESS: AtomicUsize = AtomicUsize::new(0);
+
+/// Address of the thunk table entry replaced by the hook, zero when
+/// the hook hasn't been applied
+static HOOKED_ADDRESS: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether offline mode is forced, host lookups aren't redirected
+/// while offline mode is forced
+static FORCE_OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Number of host lookups that have been redirected
+static REDIRECT_COUNT: AtomicU32 = AtomicU32::new(0);
+/// Unix timestamp in seconds of the last redirected lookup, zero
+/// when no lookups have been redirected
+static LAST_REDIRECT: AtomicU64 = AtomicU64::new(0);
+
 /// Static memory region for the host name bytes
 static mut HOST_BYTES: [u8; 21] = *b"gosredirector.ea.com\0";
 /// Static memory region storing the address bytes
+///
+/// The game uses this address to reach the redirector server, the redirector
+/// then provides the blaze server address in its instance response. The
+/// shared redirector always responds with IPv4 loopback (127.0.0.1) and
+/// [BLAZE_PORT](crate::core::servers::BLAZE_PORT), so both steps are IPv4
+/// and the blaze server must always be reachable on loopback. An IPv6
+/// redirect here would also need the instance response changed to match
 static mut ADDRESS_BYTES: [i8; 5] = [127, 0, 0, 1, 0];
 /// Static null terminated addresses array
 static mut ADDRESSES_ARRAY: [*mut i8; 2] = [unsafe { ADDRESS_BYTES.as_mut_ptr() }, null_mut()];
@@ -56,8 +91,58 @@ static mut HOST_ENT: HOSTENT = unsafe {
     }
 };
 
+/// Sets the address that gosredirector.ea.com lookups are redirected to,
+/// defaults to loopback when not set. Only the redirector step uses this
+/// address, see [ADDRESS_BYTES] for how it relates to the blaze address
+///
+/// ## Safety
+///
+/// Must be called before the host lookup hook is applied, the address
+/// bytes are read by the game thread without synchronization
+///
+/// ## Arguments
+/// * `address` - The address to redirect to
+pub unsafe fn set_redirect_address(address: Ipv4Addr) {
+    let [a, b, c, d] = address.octets();
+    *addr_of_mut!(ADDRESS_BYTES) = [a as i8, b as i8, c as i8, d as i8, 0];
+}
+
+/// Sets whether offline mode is forced, while forced the game uses
+/// the official servers even when the local servers are running
+///
+/// ## Arguments
+/// * `offline` - Whether to force offline mode
+pub fn set_force_offline(offline: bool) {
+    FORCE_OFFLINE.store(offline, Ordering::Release);
+}
+
+/// Provides whether offline mode is forced
+pub fn is_force_offline() -> bool {
+    FORCE_OFFLINE.load(Ordering::Acquire)
+}
+
+/// Provides the number of host lookups that have been redirected along
+/// with the unix timestamp of the last redirect
+pub fn redirect_stats() -> (u32, Option<u64>) {
+    let count = REDIRECT_COUNT.load(Ordering::Relaxed);
+    let last = LAST_REDIRECT.load(Ordering::Relaxed);
+    (count, if last == 0 { None } else { Some(last) })
+}
+
+/// Resets the redirected host lookup stats, used when connecting to
+/// a new server
+pub fn reset_redirect_stats() {
+    REDIRECT_COUNT.store(0, Ordering::Relaxed);
+    LAST_REDIRECT.store(0, Ordering::Relaxed);
+}
+
 /// Function used to override the normal functionality for `gethostbyname` and
-/// replace lookups for gosredirector.ea.com with localhost redirects
+/// replace lookups for gosredirector.ea.com with redirects to the local servers
+/// (or the configured redirect address)
+///
+/// Lookups are handled within [std::panic::catch_unwind] as unwinding into
+/// the game code is undefined behavior, when handling panics the actual
+/// `gethostbyname` function is used instead
 ///
 /// ## Safety
 ///
@@ -65,40 +150,124 @@ static mut HOST_ENT: HOSTENT = unsafe {
 /// only using a different pointer when required so it is considered safe
 #[no_mangle]
 pub unsafe extern "system" fn fake_gethostbyname(name: PCSTR) -> *mut HOSTENT {
+    let redirect = catch_unwind(|| redirect_host_lookup(name)).unwrap_or_else(|_| {
+        error!("Panic while handling host lookup, using actual lookup");
+        None
+    });
+
+    match redirect {
+        Some(host_ent) => host_ent,
+        // Use the actual function
+        None => gethostbyname(name),
+    }
+}
+
+/// Determines whether the host lookup for `name` should be redirected,
+/// provides the redirect [HOSTENT] if it should be
+///
+/// ## Safety
+///
+/// `name` must be a valid null terminated string
+unsafe fn redirect_host_lookup(name: PCSTR) -> Option<*mut HOSTENT> {
     // Derive the safe name from the str bytes
     let str_name = CStr::from_ptr(name.cast());
 
     debug!("Got host lookup request: {:?}", str_name);
 
     // Only handle gosredirector.ea.com domains and don't use the override unless
-    // there is running server tasks
-    if str_name.to_bytes() == b"gosredirector.ea.com" && has_server_tasks() {
+    // there is running server tasks and offline mode isn't forced
+    if str_name.to_bytes() == b"gosredirector.ea.com" && has_server_tasks() && !is_force_offline() {
         debug!("Responding with localhost redirect");
-        return addr_of_mut!(HOST_ENT);
+        REDIRECT_COUNT.fetch_add(1, Ordering::Relaxed);
+        LAST_REDIRECT.store(unix_timestamp(), Ordering::Relaxed);
+        return Some(addr_of_mut!(HOST_ENT));
     }
 
-    // Use the actual function
-    gethostbyname(name)
+    None
+}
+
+/// Overrides the address range searched for the gethostbyname call, must
+/// be called before the hook is applied
+///
+/// ## Arguments
+/// * `start` - Address to start matching from
+/// * `end`   - Address to end matching at
+pub fn set_scan_range(start: usize, end: usize) {
+    SCAN_START.store(start, Ordering::Release);
+    SCAN_END.store(end, Ordering::Release);
+}
+
+/// Provides the address range searched for the gethostbyname call
+pub fn scan_range() -> (usize, usize) {
+    (
+        SCAN_START.load(Ordering::Acquire),
+        SCAN_END.load(Ordering::Acquire),
+    )
+}
+
+/// Sets the address of the gethostbyname call skipping the pattern
+/// matching, must be called before the hook is applied. The address is
+/// read when hooking so it must be within the game image
+///
+/// ## Arguments
+/// * `address` - Address of the gethostbyname call instruction
+pub fn set_call_address(address: usize) {
+    CALL_ADDRESS.store(address, Ordering::Release);
+}
+
+/// Finds the address of the gethostbyname call, uses the manually provided
+/// address when set otherwise the address range is searched
+///
+/// ## Safety
+///
+/// Reads program memory within the address range
+unsafe fn find_call_address() -> Result<*const u8, &'static str> {
+    let address = CALL_ADDRESS.load(Ordering::Acquire);
+    if address != 0 {
+        let addr = address as *const u8;
+        if *addr != CALL {
+            warn!("Configured gethostbyname call address isn't a call");
+            return Err("the configured gethostbyname call address isn't a call instruction");
+        }
+
+        return Ok(addr);
+    }
+
+    let (start, end) = scan_range();
+
+    // Hooking the wrong call would break unrelated lookups, so ambiguous
+    // matches are refused rather than using the first match
+    find_unique_pattern(start, end, HOST_LOOKUP_MASK, HOST_LOOKUP_OP_CODES).map_err(|err| match err
+    {
+        PatternError::NotFound => {
+            warn!("Failed to find gethostbyname hook position");
+            "the gethostbyname call couldn't be found in the game"
+        }
+        PatternError::Ambiguous(addresses) => {
+            let addresses: Vec<String> = addresses
+                .iter()
+                .map(|addr| format!("{:#010x}", addr))
+                .collect();
+            warn!(
+                "gethostbyname hook pattern matched multiple addresses: {}",
+                addresses.join(", ")
+            );
+            "the gethostbyname call matched multiple places in the game"
+        }
+    })
 }
 
 /// This hook is applied to the `gethostbyname` function within the game in order
 /// to intercept IP address lookups for different domain names, allowing the client
-/// to replace them with references to 127.0.0.1 instead
+/// to replace them with references to 127.0.0.1 instead. Provides the reason the
+/// hook couldn't be applied on failure
 ///
 /// ## Safety
 ///
 /// Reading program memory is *NOT* safe but its required for pattern matching, this
 /// function mutates memory to replace function calls
-pub unsafe fn hook_host_lookup() {
-    let Some(addr) = find_pattern(
-        HOST_LOOKUP_START_OFFSET,
-        HOST_LOOKUP_END_OFFSET,
-        HOST_LOOKUP_MASK,
-        HOST_LOOKUP_OP_CODES,
-    ) else {
-        warn!("Failed to find gethostbyname hook position");
-        return;
-    };
+pub unsafe fn hook_host_lookup() -> Result<(), &'static str> {
+    let addr = find_call_address()?;
 
     debug!("Found gethostbyname @ {:#016x}", addr as usize);
 
@@ -121,6 +290,36 @@ pub unsafe fn hook_host_lookup() {
     use_memory(addr, 4, |addr| {
         // Replace the address with our faker function
         let ptr: *mut usize = addr as *mut usize;
-        *ptr = fake_gethostbyname as usize;
+        *ptr = fake_gethostbyname as *const () as usize;
     });
+
+    HOOKED_ADDRESS.store(addr as usize, Ordering::Release);
+
+    Ok(())
+}
+
+/// Provides the address of the thunk table entry replaced by the hook,
+/// [None] if the hook hasn't been applied
+pub fn hooked_address() -> Option<usize> {
+    let addr = HOOKED_ADDRESS.load(Ordering::Acquire);
+    if addr == 0 {
+        None
+    } else {
+        Some(addr)
+    }
+}
+
+/// Verifies that the host lookup hook was applied by checking that
+/// the hooked thunk table entry points to [fake_gethostbyname]
+///
+/// ## Safety
+///
+/// Reads the hooked memory address which is only valid within the game
+pub unsafe fn verify_host_lookup() -> bool {
+    let addr = HOOKED_ADDRESS.load(Ordering::Acquire);
+    if addr == 0 {
+        return false;
+    }
+
+    *(addr as *const usize) == fake_gethostbyname as *const () as usize
 }
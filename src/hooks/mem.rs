@@ -44,12 +44,78 @@ pub unsafe fn find_pattern(
     mask: &'static str,
     op_codes: &'static [u8],
 ) -> Option<*const u8> {
+    find_pattern_matches(start_offset, end_offset, mask, op_codes).next()
+}
+
+/// Provides an iterator over every address between the start and end
+/// offsets that matches the pattern
+///
+/// ## Safety
+///
+/// Reading program memory is *NOT* safe but its required for pattern matching
+///
+/// ## Arguments
+/// * start_offset - The address to start matching from
+/// * end_offset   - The address to stop matching at
+/// * mask         - The mask to use when matching opcodes
+/// * op_codes     - The op codes to match against
+pub unsafe fn find_pattern_matches(
+    start_offset: usize,
+    end_offset: usize,
+    mask: &'static str,
+    op_codes: &'static [u8],
+) -> impl Iterator<Item = *const u8> {
     // Iterate between the offsets
     (start_offset..=end_offset)
         // Cast the address to a pointer type
         .map(|addr| addr as *const u8)
         // Compare the mask at the provided address
-        .find(|addr| compare_mask(*addr, mask, op_codes))
+        .filter(move |addr| compare_mask(*addr, mask, op_codes))
+}
+
+/// Maximum number of matches collected when a pattern is ambiguous
+const MAX_AMBIGUOUS_MATCHES: usize = 8;
+
+/// Error from finding a pattern that must only match once
+#[derive(Debug)]
+pub enum PatternError {
+    /// The pattern didn't match anything
+    NotFound,
+    /// The pattern matched multiple addresses, contains up
+    /// to [MAX_AMBIGUOUS_MATCHES] of the matched addresses
+    Ambiguous(Vec<usize>),
+}
+
+/// Finds a pattern that must only match a single address between the start
+/// and end offsets. Unlike [find_pattern] the entire range is scanned, used
+/// for hooks where hooking the wrong match would crash the game
+///
+/// ## Safety
+///
+/// Reading program memory is *NOT* safe but its required for pattern matching
+///
+/// ## Arguments
+/// * start_offset - The address to start matching from
+/// * end_offset   - The address to stop matching at
+/// * mask         - The mask to use when matching opcodes
+/// * op_codes     - The op codes to match against
+pub unsafe fn find_unique_pattern(
+    start_offset: usize,
+    end_offset: usize,
+    mask: &'static str,
+    op_codes: &'static [u8],
+) -> Result<*const u8, PatternError> {
+    let matches: Vec<*const u8> = find_pattern_matches(start_offset, end_offset, mask, op_codes)
+        .take(MAX_AMBIGUOUS_MATCHES)
+        .collect();
+
+    match matches.as_slice() {
+        [] => Err(PatternError::NotFound),
+        [addr] => Ok(*addr),
+        _ => Err(PatternError::Ambiguous(
+            matches.into_iter().map(|addr| addr as usize).collect(),
+        )),
+    }
 }
 
 /// Attempts to apply virtual protect READ/WRITE access
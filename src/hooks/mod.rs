@@ -1,15 +1,140 @@
-use log::debug;
+use log::{debug, warn};
+use std::{fmt::Display, sync::Mutex};
 
 pub mod host_lookup;
 pub mod mem;
 pub mod process_event;
 
-/// Applies all hooks
+/// Status of all the hooks after they were applied
+static HOOK_STATUS: Mutex<HookStatus> = Mutex::new(HookStatus {
+    host_lookup: HookState::NotApplied,
+    process_event: HookState::NotApplied,
+});
+
+/// State of an individual hook
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookState {
+    /// Hook hasn't been applied (Unsupported game build)
+    NotApplied,
+    /// Hook was applied and verified
+    Applied,
+    /// Hook failed to apply, contains the reason it failed
+    Failed(&'static str),
+}
+
+impl Display for HookState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            HookState::NotApplied => "NOT APPLIED",
+            HookState::Applied => "OK",
+            HookState::Failed(_) => "FAILED",
+        })
+    }
+}
+
+/// Status of all the hooks
+#[derive(Debug, Clone, Copy)]
+pub struct HookStatus {
+    /// Host lookup redirect hook (Critical)
+    pub host_lookup: HookState,
+    /// Process event hook for handling game events
+    pub process_event: HookState,
+}
+
+impl HookStatus {
+    /// Whether any critical hook failed to apply, the host lookup hook
+    /// is required for the game to connect through the local servers
+    pub fn has_critical_failure(&self) -> bool {
+        matches!(self.host_lookup, HookState::Failed(_))
+    }
+
+    /// Creates a summary of the hooks that failed to apply and the
+    /// functionality that is unavailable because of it, [None] if
+    /// no hooks failed
+    pub fn failure_summary(&self) -> Option<String> {
+        let hooks = [
+            (
+                self.host_lookup,
+                "EA server redirect could not be installed, online play will not be \
+                proxied through Pocket Relay",
+            ),
+            (
+                self.process_event,
+                "Game event hook could not be installed, server messages will not be \
+                shown in game",
+            ),
+        ];
+
+        let failures: Vec<String> = hooks
+            .into_iter()
+            .filter_map(|(state, impact)| match state {
+                HookState::Failed(reason) => Some(format!("- {impact} ({reason})")),
+                _ => None,
+            })
+            .collect();
+
+        if failures.is_empty() {
+            None
+        } else {
+            Some(failures.join("\n"))
+        }
+    }
+}
+
+impl Display for HookStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Host redirect: {}, Process event: {}",
+            self.host_lookup, self.process_event
+        )
+    }
+}
+
+/// Provides the current status of the hooks
+pub fn hook_status() -> HookStatus {
+    match HOOK_STATUS.lock() {
+        Ok(value) => *value,
+        Err(err) => *err.into_inner(),
+    }
+}
+
+/// Determines the [HookState] from the result of applying a hook and
+/// verifying that the hook is present
+///
+/// ## Arguments
+/// * `result`   - The result of applying the hook
+/// * `verified` - Whether the hook was found after applying
+fn hook_state(result: Result<(), &'static str>, verified: bool) -> HookState {
+    match result {
+        Err(reason) => HookState::Failed(reason),
+        Ok(()) if verified => HookState::Applied,
+        Ok(()) => HookState::Failed("the hook wasn't present after being applied"),
+    }
+}
+
+/// Applies all hooks, verifying that each hook was applied and storing
+/// the resulting [HookStatus]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe fn apply_hooks() {
     debug!("apply host lookup");
-    host_lookup::hook_host_lookup();
+    let host_lookup_result = host_lookup::hook_host_lookup();
     debug!("apply process event hook");
-    process_event::hook_process_event();
-    debug!("all hooks applied")
+    let process_event_result = process_event::hook_process_event();
+    debug!("all hooks applied");
+
+    let status = HookStatus {
+        host_lookup: hook_state(host_lookup_result, host_lookup::verify_host_lookup()),
+        process_event: hook_state(process_event_result, process_event::verify_process_event()),
+    };
+
+    if status.has_critical_failure() {
+        warn!("Critical hooks failed to apply: {}", status);
+    } else {
+        debug!("Hook status: {}", status);
+    }
+
+    if let Ok(mut value) = HOOK_STATUS.lock() {
+        *value = status;
+    }
 }
@@ -1,11 +1,24 @@
 use super::mem::use_memory;
-use crate::game::{
-    core::{FString, UFunction, UObject, UObjectExt},
-    sfxgame::{FSFXOnlineMOTDInfo, USFXOnlineComponentUI},
+use crate::{
+    config::unix_timestamp,
+    game::{
+        core::{FString, UFunction, UObject, UObjectExt},
+        sfxgame::{FSFXOnlineMOTDInfo, USFXOnlineComponentUI},
+    },
 };
-use log::debug;
+use log::{debug, error, warn};
 use serde::{Deserialize, Serialize};
-use std::os::raw::c_void;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    os::raw::c_void,
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr::addr_of,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
 use windows_sys::Win32::System::Memory::{
     VirtualAlloc, MEM_COMMIT, MEM_RESERVE, PAGE_EXECUTE_READWRITE,
 };
@@ -16,42 +29,110 @@ type ProcessEvent =
 // Original function for ProcessEvent
 static mut PROCESS_EVENT_ORIGINAL: Option<ProcessEvent> = None;
 
-/// Memory address the process event function is stored at
-const PROCESS_EVENT_OFFSET: usize = 0x00453120;
+/// Whether events should be traced instead of handled
+static TRACE_EVENTS: AtomicBool = AtomicBool::new(false);
 
-/// Hooks the game [ProcessEvent] function to use [fake_process_event] instead
-/// to allow processing events that occur in the game
-#[allow(clippy::missing_safety_doc)]
-pub unsafe fn hook_process_event() {
-    const JMP: u8 =  0xE9 /* jmp */;
-    const JMP_SIZE: usize = 5; // Size of a near jump instruction in x86
+/// Minimum time between traces of the same function
+const TRACE_EVENT_INTERVAL: Duration = Duration::from_secs(5);
 
-    let target = PROCESS_EVENT_OFFSET as *const u8 as *mut u8;
-    let hook = fake_process_event as *const u8;
+/// Last trace time and the number of calls since for each traced function
+static TRACED_EVENTS: Mutex<BTreeMap<String, (Instant, u32)>> = Mutex::new(BTreeMap::new());
 
-    let mut original_bytes: [u8; JMP_SIZE] = [0; JMP_SIZE];
+/// Maximum number of server messages to keep in the history
+const MESSAGE_HISTORY_LIMIT: usize = 20;
 
-    // Store the original function bytes that will be replaced with a jump
-    std::ptr::copy_nonoverlapping(target, original_bytes.as_mut_ptr(), original_bytes.len());
+/// History of recent server messages, most recent first
+static MESSAGE_HISTORY: Mutex<VecDeque<ServerMessage>> = Mutex::new(VecDeque::new());
 
-    debug!("store original instructions {:?}", original_bytes);
+/// Title of the server welcome message
+const WELCOME_TITLE: &str = "Message of the Day";
+/// Tracking ID used for the server welcome message
+const WELCOME_TRACKING_ID: i32 = 0x5052_4D44;
 
-    // Determine the offset to jump to the hooked function
-    let relative_offset = hook as i32 - (target as i32 + JMP_SIZE as i32);
+/// Server welcome message waiting to be displayed in the game
+static WELCOME_MESSAGE: Mutex<Option<String>> = Mutex::new(None);
 
-    debug!("relative offset {:#016x}", relative_offset);
+/// Title of the server version change warning
+const VERSION_WARNING_TITLE: &str = "Server Updated";
+/// Tracking ID used for the server version change warning
+const VERSION_WARNING_TRACKING_ID: i32 = 0x5052_5657;
 
-    use_memory(target, JMP_SIZE, |mem| {
-        // Set the jump instruction
-        *mem = JMP;
+/// Server version change warning waiting to be displayed in the game, kept
+/// separate from the [WELCOME_MESSAGE] so neither replaces the other
+static VERSION_WARNING: Mutex<Option<String>> = Mutex::new(None);
 
-        // Set the jump offset
-        let jump_addr = mem.byte_add(1).cast::<i32>();
-        *jump_addr = relative_offset.to_le();
-    });
+/// Default memory address the process event function is stored at
+pub const DEFAULT_PROCESS_EVENT_OFFSET: usize = 0x00453120;
+
+/// Memory address the process event function is stored at, replaced
+/// when the address is manually overridden
+static PROCESS_EVENT_OFFSET: AtomicUsize = AtomicUsize::new(DEFAULT_PROCESS_EVENT_OFFSET);
 
-    // Calculate the address of the original function after the JMP instruction
-    let trampoline_size = JMP_SIZE;
+/// Provides the memory address of the process event function
+pub fn process_event_offset() -> usize {
+    PROCESS_EVENT_OFFSET.load(Ordering::Acquire)
+}
+
+/// Overrides the memory address of the process event function, must be
+/// called before the hook is applied
+///
+/// ## Arguments
+/// * `offset` - The memory address of the process event function
+pub fn set_process_event_offset(offset: usize) {
+    PROCESS_EVENT_OFFSET.store(offset, Ordering::Release);
+}
+
+/// Jump instruction opcode
+const JMP: u8 =  0xE9 /* jmp */;
+/// Size of a near jump instruction in x86
+const JMP_SIZE: usize = 5;
+
+/// Checks that the `prologue` bytes at the start of the process event function
+/// can be moved to the trampoline, the bytes are copied without being
+/// relocated so they must not be a relative jump or call. Bytes that can't
+/// start a function (padding, returns) indicate the offset is wrong for this
+/// game build. Provides the reason the bytes can't be used
+///
+/// ## Arguments
+/// * `prologue` - The bytes that will be replaced with the jump
+fn check_prologue(prologue: &[u8; JMP_SIZE]) -> Result<(), &'static str> {
+    match prologue[0] {
+        // Another hook has already replaced the function start
+        JMP | 0xEB => Err("the process event function is already hooked"),
+        // Relative call or conditional jump can't be moved to the trampoline
+        0xE8 | 0x70..=0x7F => Err("the process event function starts with a relative branch"),
+        0x0F if (0x80..=0x8F).contains(&prologue[1]) => {
+            Err("the process event function starts with a relative branch")
+        }
+        // Padding and returns are never the start of the function
+        0x00 | 0x90 | 0xCC | 0xC2 | 0xC3 => {
+            Err("the process event offset doesn't point to a function")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Hooks the game [ProcessEvent] function to use [fake_process_event] instead
+/// to allow processing events that occur in the game. Provides the reason
+/// the hook couldn't be applied on failure
+#[allow(clippy::missing_safety_doc)]
+pub unsafe fn hook_process_event() -> Result<(), &'static str> {
+    let target = process_event_offset() as *const u8 as *mut u8;
+    let hook = fake_process_event as *const u8;
+
+    // Check the bytes that will be replaced before changing anything
+    let prologue = target.cast::<[u8; JMP_SIZE]>().read_unaligned();
+    if let Err(reason) = check_prologue(&prologue) {
+        warn!(
+            "Refusing to hook process event at {:#010x} (bytes {:02x?}): {}",
+            target as usize, prologue, reason
+        );
+        return Err(reason);
+    }
+
+    // Allocate the trampoline before replacing any instructions so a failed
+    // allocation leaves the original function untouched
+    let trampoline_size = JMP_SIZE * 2;
     let trampoline = VirtualAlloc(
         std::ptr::null_mut(),
         trampoline_size,
@@ -60,9 +141,17 @@ pub unsafe fn hook_process_event() {
     );
 
     if trampoline.is_null() {
-        panic!("Failed to allocate memory for trampoline");
+        warn!("Failed to allocate memory for trampoline");
+        return Err("memory for the original function trampoline couldn't be allocated");
     }
 
+    let mut original_bytes: [u8; JMP_SIZE] = [0; JMP_SIZE];
+
+    // Store the original function bytes that will be replaced with a jump
+    std::ptr::copy_nonoverlapping(target, original_bytes.as_mut_ptr(), original_bytes.len());
+
+    debug!("store original instructions {:?}", original_bytes);
+
     // Determine the offset to jump back
     let jump_back_offset = target as i32 - (trampoline as i32 + JMP_SIZE as i32);
 
@@ -84,6 +173,87 @@ pub unsafe fn hook_process_event() {
 
     // Save the original function pointer, adjusted to skip the JMP instruction
     PROCESS_EVENT_ORIGINAL = Some(std::mem::transmute::<*mut c_void, ProcessEvent>(trampoline));
+
+    // Determine the offset to jump to the hooked function
+    let relative_offset = hook as i32 - (target as i32 + JMP_SIZE as i32);
+
+    debug!("relative offset {:#016x}", relative_offset);
+
+    use_memory(target, JMP_SIZE, |mem| {
+        // Set the jump instruction
+        *mem = JMP;
+
+        // Set the jump offset
+        let jump_addr = mem.byte_add(1).cast::<i32>();
+        *jump_addr = relative_offset.to_le();
+    });
+
+    Ok(())
+}
+
+/// Provides the address of the trampoline used to call the original
+/// process event function, [None] if the hook hasn't been applied
+pub fn trampoline_address() -> Option<usize> {
+    unsafe { *addr_of!(PROCESS_EVENT_ORIGINAL) }.map(|original| original as usize)
+}
+
+/// Verifies that the process event hook was applied by checking that the
+/// function starts with a jump to [fake_process_event] and that the original
+/// function trampoline was created
+///
+/// ## Safety
+///
+/// Reads the game memory at [process_event_offset] which is only valid
+/// within the game
+pub unsafe fn verify_process_event() -> bool {
+    let target = process_event_offset() as *const u8;
+    let hook = fake_process_event as *const u8;
+
+    // Determine the offset the jump should have
+    let relative_offset = hook as i32 - (target as i32 + JMP_SIZE as i32);
+    let jump_offset = target.add(1).cast::<i32>().read_unaligned();
+
+    (*addr_of!(PROCESS_EVENT_ORIGINAL)).is_some()
+        && *target == JMP
+        && jump_offset == relative_offset
+}
+
+/// Sets whether events should be traced. While tracing every function
+/// name is logged (throttled per function) and events are passed directly
+/// to the game without being handled
+///
+/// ## Arguments
+/// * `enabled` - Whether to trace events
+pub fn set_trace_events(enabled: bool) {
+    TRACE_EVENTS.store(enabled, Ordering::Relaxed);
+}
+
+/// Logs the name of a called function, each function is only logged
+/// once per [TRACE_EVENT_INTERVAL] along with the number of calls since
+///
+/// ## Arguments
+/// * `name` - The full name of the called function
+fn trace_event(name: &str) {
+    let traced = &mut *match TRACED_EVENTS.lock() {
+        Ok(value) => value,
+        Err(_) => return,
+    };
+
+    let now = Instant::now();
+
+    let Some((last_trace, calls)) = traced.get_mut(name) else {
+        debug!("Event: {}", name);
+        traced.insert(name.to_string(), (now, 0));
+        return;
+    };
+
+    *calls += 1;
+
+    if now.duration_since(*last_trace) >= TRACE_EVENT_INTERVAL {
+        debug!("Event: {} ({} calls)", name, calls);
+        *last_trace = now;
+        *calls = 0;
+    }
 }
 
 /// JSON structure for a system terminal message the server can
@@ -104,6 +274,68 @@ pub struct SystemTerminalMessage {
     priority: i32,
 }
 
+/// Server message that was displayed in the game
+#[derive(Debug, Clone)]
+pub struct ServerMessage {
+    /// Unix timestamp in seconds of when the message was received
+    pub received_at: u64,
+    /// Title of the message
+    pub title: String,
+    /// Message content
+    pub message: String,
+}
+
+/// Adds a message to the server message history
+///
+/// ## Arguments
+/// * `message` - The received message
+fn push_message_history(message: &SystemTerminalMessage) {
+    let history = &mut *match MESSAGE_HISTORY.lock() {
+        Ok(value) => value,
+        Err(_) => return,
+    };
+
+    history.push_front(ServerMessage {
+        received_at: unix_timestamp(),
+        title: message.title.clone(),
+        message: message.message.clone(),
+    });
+    history.truncate(MESSAGE_HISTORY_LIMIT);
+}
+
+/// Sets the welcome message to display in the in-game terminal, the message
+/// is displayed once alongside the next notification the game displays.
+/// [None] discards any message that hasn't been displayed
+///
+/// ## Arguments
+/// * `message` - The welcome message
+pub fn set_welcome_message(message: Option<String>) {
+    if let Ok(mut value) = WELCOME_MESSAGE.lock() {
+        *value = message;
+    }
+}
+
+/// Sets the server version change warning to display in the in-game
+/// terminal, displayed once alongside the next notification the game
+/// displays independently of the welcome message. [None] discards any
+/// warning that hasn't been displayed
+///
+/// ## Arguments
+/// * `message` - The warning message
+pub fn set_version_warning(message: Option<String>) {
+    if let Ok(mut value) = VERSION_WARNING.lock() {
+        *value = message;
+    }
+}
+
+/// Provides the recently received server messages, most recent first
+pub fn message_history() -> Vec<ServerMessage> {
+    match MESSAGE_HISTORY.lock() {
+        Ok(value) => value.iter().cloned().collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
 /// Calls the original ProcessEvent function
 ///
 /// # Safety
@@ -131,9 +363,36 @@ struct OnDisplayNotificationParams {
     info: FSFXOnlineMOTDInfo,
 }
 
+/// Handler for a server command line within a notification, receives the
+/// line content after the prefix and returns whether a message was displayed
+type NotificationHandler = fn(&mut USFXOnlineComponentUI, &FSFXOnlineMOTDInfo, &str) -> bool;
+
+/// Server command prefixes that can appear on a line of a notification
+/// message along with the handler for the line
+const NOTIFICATION_HANDLERS: &[(&str, NotificationHandler)] = &[
+    ("[SYSTEM_TERMINAL]", handle_system_terminal_line),
+    ("[TOAST]", handle_toast_line),
+    ("[BANNER]", handle_banner_line),
+];
+
+/// Tracking ID used for server banners, shared so that a new banner
+/// replaces the previous one
+const BANNER_TRACKING_ID: i32 = 0x5052_424E;
+
+/// JSON structure for a banner message the server can send
+#[derive(Deserialize)]
+struct BannerMessage {
+    /// Title displayed on the terminal
+    title: String,
+    /// Message displayed on the terminal
+    message: String,
+}
+
 /// Handles incoming notification display calls, adds additional logic to
-/// check for special JSON payload messages send by Pocket Relay to display
-/// custom messages
+/// check for server command lines sent by Pocket Relay to display custom
+/// messages. Each line is checked against the [NOTIFICATION_HANDLERS],
+/// lines with unknown prefixes are ignored and when nothing is handled
+/// the original notification is displayed unchanged
 fn process_on_display_notification(
     this: &mut USFXOnlineComponentUI,
     params: &OnDisplayNotificationParams,
@@ -144,29 +403,192 @@ fn process_on_display_notification(
     // Extract the message
     let original_message = &info.message.to_string();
 
-    // Split the payload at new lines
-    let lines = original_message.lines();
+    let mut handled = false;
 
-    // Find a system message line
-    let system_message = lines
-        .into_iter()
-        // Find a system message line
-        .find_map(|line| line.strip_prefix("[SYSTEM_TERMINAL]"));
+    // Multiple commands can be sent in the same notification
+    for line in original_message.lines() {
+        let Some((handler, value)) = NOTIFICATION_HANDLERS
+            .iter()
+            .find_map(|(prefix, handler)| Some((handler, line.strip_prefix(prefix)?)))
+        else {
+            continue;
+        };
 
-    let system_message = match system_message {
-        Some(value) => value,
-        // No system message found
-        None => return false,
-    };
+        handled |= handler(this, info, value);
+    }
 
+    handled
+}
+
+/// Handles a `[SYSTEM_TERMINAL]` line, the line contains a JSON
+/// [SystemTerminalMessage] that is displayed in the in-game terminal
+///
+/// ## Arguments
+/// * `this`  - The game UI component
+/// * `info`  - The original notification
+/// * `value` - The line content
+fn handle_system_terminal_line(
+    this: &mut USFXOnlineComponentUI,
+    _info: &FSFXOnlineMOTDInfo,
+    value: &str,
+) -> bool {
     // Parse the system message
-    let message = match serde_json::from_str::<SystemTerminalMessage>(system_message) {
+    let message = match serde_json::from_str::<SystemTerminalMessage>(value) {
         Ok(value) => value,
         // Ignore malformed system message
         Err(_) => return false,
     };
 
-    // Send custom message instead
+    // Send custom message instead, falls back to the original message if
+    // the notification function couldn't be called
+    display_message(this, message)
+}
+
+/// Handles a `[TOAST]` line, the line contains plain text that is
+/// displayed using the title and display settings of the original
+/// notification
+///
+/// ## Arguments
+/// * `this`  - The game UI component
+/// * `info`  - The original notification
+/// * `value` - The line content
+fn handle_toast_line(
+    this: &mut USFXOnlineComponentUI,
+    info: &FSFXOnlineMOTDInfo,
+    value: &str,
+) -> bool {
+    let message = value.trim();
+    if message.is_empty() {
+        return false;
+    }
+
+    display_message(
+        this,
+        SystemTerminalMessage {
+            title: info.title.to_string(),
+            message: message.to_string(),
+            image: info.image.to_string(),
+            ty: info.ty,
+            tracking_id: info.tracking_id,
+            priority: info.priority,
+        },
+    )
+}
+
+/// Handles a `[BANNER]` line, the line contains a JSON [BannerMessage]
+/// that is displayed using [BANNER_TRACKING_ID] so only the latest
+/// banner is shown
+///
+/// ## Arguments
+/// * `this`  - The game UI component
+/// * `info`  - The original notification
+/// * `value` - The line content
+fn handle_banner_line(
+    this: &mut USFXOnlineComponentUI,
+    info: &FSFXOnlineMOTDInfo,
+    value: &str,
+) -> bool {
+    let banner = match serde_json::from_str::<BannerMessage>(value) {
+        Ok(value) => value,
+        // Ignore malformed banner message
+        Err(_) => return false,
+    };
+
+    display_message(
+        this,
+        SystemTerminalMessage {
+            title: banner.title,
+            message: banner.message,
+            image: String::new(),
+            ty: info.ty,
+            tracking_id: BANNER_TRACKING_ID,
+            priority: info.priority,
+        },
+    )
+}
+
+/// Displays the pending welcome message if there is one, the game UI
+/// component is only available while the game is displaying a notification
+/// so the welcome message is displayed alongside the `info` notification
+///
+/// ## Arguments
+/// * `this` - The game UI component
+/// * `info` - The notification the game is displaying
+fn display_welcome_message(this: &mut USFXOnlineComponentUI, info: &FSFXOnlineMOTDInfo) {
+    display_pending_message(
+        this,
+        info,
+        &WELCOME_MESSAGE,
+        WELCOME_TITLE,
+        WELCOME_TRACKING_ID,
+    );
+}
+
+/// Displays the pending server version change warning if there is one,
+/// displayed alongside the `info` notification like the welcome message
+///
+/// ## Arguments
+/// * `this` - The game UI component
+/// * `info` - The notification the game is displaying
+fn display_version_warning(this: &mut USFXOnlineComponentUI, info: &FSFXOnlineMOTDInfo) {
+    display_pending_message(
+        this,
+        info,
+        &VERSION_WARNING,
+        VERSION_WARNING_TITLE,
+        VERSION_WARNING_TRACKING_ID,
+    );
+}
+
+/// Takes the message waiting in `pending` and displays it alongside the
+/// `info` notification
+///
+/// ## Arguments
+/// * `this`        - The game UI component
+/// * `info`        - The notification the game is displaying
+/// * `pending`     - The message waiting to be displayed
+/// * `title`       - Title to display the message with
+/// * `tracking_id` - Tracking ID to display the message with
+fn display_pending_message(
+    this: &mut USFXOnlineComponentUI,
+    info: &FSFXOnlineMOTDInfo,
+    pending: &Mutex<Option<String>>,
+    title: &str,
+    tracking_id: i32,
+) {
+    // Taken before displaying as displaying calls back into this hook
+    let message = match pending.lock() {
+        Ok(mut value) => value.take(),
+        Err(_) => return,
+    };
+
+    let Some(message) = message else {
+        return;
+    };
+
+    // Displayed in the same place as the game notification
+    display_message(
+        this,
+        SystemTerminalMessage {
+            title: title.to_string(),
+            message,
+            image: String::new(),
+            ty: info.ty,
+            tracking_id,
+            priority: info.priority,
+        },
+    );
+}
+
+/// Displays a message in the in-game terminal and adds it to the message
+/// history, returns whether the notification function was called
+///
+/// ## Arguments
+/// * `this`    - The game UI component
+/// * `message` - The message to display
+fn display_message(this: &mut USFXOnlineComponentUI, message: SystemTerminalMessage) -> bool {
+    push_message_history(&message);
+
     unsafe {
         this.event_on_display_notification(FSFXOnlineMOTDInfo {
             title: FString::from_string(message.title),
@@ -177,15 +599,17 @@ fn process_on_display_notification(
             bw_ent_id: 0,
             offer_id: 0,
             ty: message.ty,
-        });
+        })
     }
-
-    true
 }
 
 /// Hooked ProcessEvent function that allows extending the games
 /// behavior by listing for specific events
 ///
+/// Events are handled within [std::panic::catch_unwind] as unwinding into
+/// the game code is undefined behavior, when handling panics the event is
+/// passed to the original function instead
+///
 /// # Safety
 ///
 /// Checks are made on pointers that are used, most events are forwarded
@@ -197,18 +621,44 @@ pub unsafe extern "thiscall" fn fake_process_event(
     params: *mut c_void,
     result: *mut c_void,
 ) {
+    let handled = catch_unwind(AssertUnwindSafe(|| {
+        handle_process_event(object, func, params)
+    }))
+    .unwrap_or_else(|_| {
+        error!("Panic while handling process event, using original function");
+        false
+    });
+
+    if !handled {
+        process_event(object, func, params, result);
+    }
+}
+
+/// Handles a process event call, returns whether the event was handled
+/// and should not be passed to the original function
+///
+/// # Safety
+///
+/// Checks are made on the pointers that are used
+unsafe fn handle_process_event(
+    object: *mut UObject,
+    func: *mut UFunction,
+    params: *mut c_void,
+) -> bool {
     // Ensure func is not null
-    let func_ref = match func.as_ref() {
-        Some(value) => value,
-        None => {
-            process_event(object, func, params, result);
-            return;
-        }
+    let Some(func_ref) = func.as_ref() else {
+        return false;
     };
 
     // Find the full name of the function that was called
     let name = func_ref.as_object_ref().get_full_name();
 
+    // Events are passed straight through while tracing
+    if TRACE_EVENTS.load(Ordering::Relaxed) {
+        trace_event(&name);
+        return false;
+    }
+
     // Hook existing display notification event code
     if name.contains("Function SFXGame.SFXOnlineComponentUI.OnDisplayNotification") {
         // Cast the types
@@ -217,11 +667,11 @@ pub unsafe extern "thiscall" fn fake_process_event(
 
         // Try handle a notification
         if let (Some(this), Some(params)) = (this, params) {
-            if process_on_display_notification(this, params) {
-                return;
-            }
+            display_welcome_message(this, &params.info);
+            display_version_warning(this, &params.info);
+            return process_on_display_notification(this, params);
         }
     }
 
-    process_event(object, func, params, result);
+    false
 }
@@ -0,0 +1,114 @@
+//! Hotkey for toggling offline mode while in game, the key state is polled
+//! as the game window isn't owned by the plugin
+
+use native_windows_gui::NoticeSender;
+use std::{str::FromStr, time::Duration};
+use windows_sys::Win32::UI::{
+    Input::KeyboardAndMouse::{
+        GetAsyncKeyState, VIRTUAL_KEY, VK_CONTROL, VK_F1, VK_MENU, VK_SHIFT,
+    },
+    WindowsAndMessaging::{GetForegroundWindow, GetWindowThreadProcessId},
+};
+
+/// Interval the hotkey state is polled at
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Key combination parsed from the config (e.g. "Ctrl+Shift+O")
+#[derive(Debug, Clone)]
+pub struct Hotkey {
+    /// Keys that must all be held
+    keys: Vec<VIRTUAL_KEY>,
+}
+
+impl FromStr for Hotkey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let keys = s
+            .split('+')
+            .map(|key| parse_key(key.trim()).ok_or_else(|| format!("Unknown key \"{}\"", key)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // At least one key that isn't a modifier is required
+        if keys
+            .iter()
+            .all(|key| matches!(*key, VK_CONTROL | VK_SHIFT | VK_MENU))
+        {
+            return Err("Hotkey must include a non modifier key".to_string());
+        }
+
+        Ok(Self { keys })
+    }
+}
+
+impl Hotkey {
+    /// Checks whether all the hotkey keys are currently held
+    fn is_pressed(&self) -> bool {
+        self.keys
+            .iter()
+            // Most significant bit is set while the key is held
+            .all(|key| unsafe { GetAsyncKeyState(*key as i32) } < 0)
+    }
+}
+
+/// Parses a single key name, modifiers (Ctrl, Shift, Alt), letters,
+/// digits and function keys (F1-F12) are supported
+///
+/// ## Arguments
+/// * `key` - The key name
+fn parse_key(key: &str) -> Option<VIRTUAL_KEY> {
+    let upper = key.to_ascii_uppercase();
+
+    match upper.as_str() {
+        "CTRL" | "CONTROL" => return Some(VK_CONTROL),
+        "SHIFT" => return Some(VK_SHIFT),
+        "ALT" => return Some(VK_MENU),
+        _ => {}
+    }
+
+    // Letter and digit virtual key codes match their ASCII values
+    if let [key] = upper.as_bytes() {
+        return key.is_ascii_alphanumeric().then_some(*key as VIRTUAL_KEY);
+    }
+
+    let number: VIRTUAL_KEY = upper.strip_prefix('F')?.parse().ok()?;
+    (1..=12).contains(&number).then_some(VK_F1 + number - 1)
+}
+
+/// Checks whether a window belonging to the game process is focused,
+/// the hotkey is ignored while other applications are being used
+fn is_game_focused() -> bool {
+    let window = unsafe { GetForegroundWindow() };
+    if window == 0 {
+        return false;
+    }
+
+    let mut process_id = 0;
+    unsafe { GetWindowThreadProcessId(window, &mut process_id) };
+
+    process_id == std::process::id()
+}
+
+/// Starts a background thread that notifies the `notice` each time the
+/// `hotkey` is pressed while the game is focused
+///
+/// ## Arguments
+/// * `hotkey` - The hotkey to listen for
+/// * `notice` - Sender notified when the hotkey is pressed
+pub fn start_hotkey_listener(hotkey: Hotkey, notice: NoticeSender) {
+    std::thread::spawn(move || {
+        let mut was_pressed = false;
+
+        loop {
+            let pressed = hotkey.is_pressed() && is_game_focused();
+
+            // Only notify once per press
+            if pressed && !was_pressed {
+                notice.notice();
+            }
+
+            was_pressed = pressed;
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    });
+}
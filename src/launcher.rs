@@ -0,0 +1,211 @@
+//! # Launcher
+//!
+//! C ABI functions that allow external launchers to connect and disconnect
+//! the plugin without the user interacting with the UI. The functions can
+//! be called from any thread once the plugin runtime has started
+
+use crate::{
+    config::read_config_file,
+    connection::{set_connection_state, ConnectionState},
+    core::{api::LookupData, reqwest::Client, servers::has_server_tasks},
+    diagnostics::{record_error, ErrorCategory},
+    servers::{self, ConnectOptions},
+    threads::resume_all_threads,
+};
+use log::{debug, error};
+use native_windows_gui::NoticeSender;
+use std::{
+    ffi::{c_char, CStr},
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Mutex, OnceLock,
+    },
+    time::Duration,
+};
+use tokio::runtime::Handle;
+
+/// The request completed successfully
+pub const PR_OK: i32 = 0;
+/// The plugin runtime hasn't started yet
+pub const PR_NOT_READY: i32 = 1;
+/// The provided argument was invalid
+pub const PR_INVALID_ARGUMENT: i32 = 2;
+/// Connecting to the server failed
+pub const PR_CONNECT_FAILED: i32 = 3;
+/// Connecting to the server didn't complete within [CONNECT_TIMEOUT]
+pub const PR_TIMED_OUT: i32 = 4;
+
+/// Maximum time [pr_connect] waits for the connection to complete
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// State required for launcher requests
+struct LauncherState {
+    /// Handle to the plugin tokio runtime
+    runtime: Handle,
+    /// HTTP client for connecting to servers
+    http_client: Client,
+}
+
+/// Launcher state, set once the plugin runtime has started
+static LAUNCHER_STATE: OnceLock<LauncherState> = OnceLock::new();
+
+/// Sender notifying the UI that a launcher request changed the connection
+static UI_NOTICE: OnceLock<NoticeSender> = OnceLock::new();
+
+/// Server connected to by the last launcher request, taken by the UI
+static LAUNCHER_CONNECTION: Mutex<Option<LookupData>> = Mutex::new(None);
+
+/// Provides the runtime handle and HTTP client used when handling
+/// launcher requests, must be called once the runtime has started
+///
+/// ## Arguments
+/// * `runtime`     - Handle to the plugin tokio runtime
+/// * `http_client` - The HTTP client to connect with
+pub fn init(runtime: Handle, http_client: Client) {
+    let _ = LAUNCHER_STATE.set(LauncherState {
+        runtime,
+        http_client,
+    });
+}
+
+/// Sets the `notice` used to notify the UI when a launcher request
+/// changes the connection, the UI reads the new state when notified
+///
+/// ## Arguments
+/// * `notice` - Sender notified after launcher requests
+pub fn set_ui_notice(notice: NoticeSender) {
+    let _ = UI_NOTICE.set(notice);
+}
+
+/// Takes the server connected to by the last launcher request
+pub fn take_launcher_connection() -> Option<LookupData> {
+    LAUNCHER_CONNECTION.lock().ok()?.take()
+}
+
+/// Notifies the UI that a launcher request changed the connection
+fn notify_ui() {
+    if let Some(notice) = UI_NOTICE.get() {
+        notice.notice();
+    }
+}
+
+/// Connects to the server at the provided connection `url`, any existing
+/// servers are stopped first. Resumes the game threads once connected.
+///
+/// Waits up to [CONNECT_TIMEOUT] for the connection, the connection is
+/// cancelled if it takes longer. Returns [PR_OK] on success or one of the
+/// other `PR_` status codes
+///
+/// ## Safety
+///
+/// `url` must be a valid pointer to a null terminated UTF-8 string
+#[no_mangle]
+pub unsafe extern "C" fn pr_connect(url: *const c_char) -> i32 {
+    let status = connect(url);
+    notify_ui();
+    status
+}
+
+/// Handles a [pr_connect] request, the UI is notified by the caller
+///
+/// ## Safety
+///
+/// `url` must be a valid pointer to a null terminated UTF-8 string
+unsafe fn connect(url: *const c_char) -> i32 {
+    if url.is_null() {
+        return PR_INVALID_ARGUMENT;
+    }
+
+    let Ok(url) = CStr::from_ptr(url).to_str() else {
+        return PR_INVALID_ARGUMENT;
+    };
+
+    let Some(state) = LAUNCHER_STATE.get() else {
+        return PR_NOT_READY;
+    };
+
+    debug!("Launcher requested connection to {}", url);
+
+    if has_server_tasks() {
+        servers::stop_all_servers();
+        set_connection_state(ConnectionState::Disconnected);
+    }
+
+    if !set_connection_state(ConnectionState::Connecting) {
+        return PR_NOT_READY;
+    }
+
+    notify_ui();
+
+    let options = read_config_file()
+        .map(|config| ConnectOptions::from_config(&config))
+        .unwrap_or_default();
+
+    let http_client = state.http_client.clone();
+    let target = servers::normalize_target(url);
+
+    // Connect on the runtime and wait for the result, the runtime is not
+    // entered directly so this is safe to call from any thread
+    let (tx, rx) = mpsc::channel();
+    let task = state.runtime.spawn(async move {
+        let result = servers::connect(http_client, target, options).await;
+        let _ = tx.send(result);
+    });
+
+    match rx.recv_timeout(CONNECT_TIMEOUT) {
+        Ok(Ok(connected)) => {
+            debug!("Launcher connected to {}", connected.lookup.url);
+            if let Some(message) = connected.banner.message() {
+                debug!("Server banner: {}", message);
+            }
+            set_connection_state(ConnectionState::Connected);
+
+            if let Ok(mut value) = LAUNCHER_CONNECTION.lock() {
+                *value = Some(connected.lookup);
+            }
+
+            // Resume game threads
+            resume_all_threads();
+
+            PR_OK
+        }
+        Ok(Err(err)) => {
+            error!("Launcher failed to connect: {}", err);
+            set_connection_state(ConnectionState::Disconnected);
+            record_error(ErrorCategory::Connection, err.to_string());
+            PR_CONNECT_FAILED
+        }
+        Err(RecvTimeoutError::Timeout) => {
+            // Dropping the connection stops any servers it started
+            task.abort();
+
+            error!("Launcher connection timed out");
+            set_connection_state(ConnectionState::Disconnected);
+            record_error(ErrorCategory::Connection, "Connection timed out");
+            PR_TIMED_OUT
+        }
+        // Runtime dropped the task before completing
+        Err(RecvTimeoutError::Disconnected) => {
+            set_connection_state(ConnectionState::Disconnected);
+            PR_NOT_READY
+        }
+    }
+}
+
+/// Stops all running servers
+///
+/// Returns [PR_OK] on success or [PR_NOT_READY] if the runtime hasn't started
+#[no_mangle]
+pub extern "C" fn pr_disconnect() -> i32 {
+    if LAUNCHER_STATE.get().is_none() {
+        return PR_NOT_READY;
+    }
+
+    debug!("Launcher requested disconnect");
+
+    servers::stop_all_servers();
+    set_connection_state(ConnectionState::Disconnected);
+    notify_ui();
+
+    PR_OK
+}
@@ -1,20 +1,31 @@
 #![warn(unused_crate_dependencies)]
 
-use config::read_config_file;
+use config::{parse_address, read_config_file, AdvancedConfig};
 use core::{
-    api::{create_http_client, read_client_identity},
+    api::read_client_identity,
     reqwest::{Client, Identity},
 };
-use log::error;
+use event_log::EventKind;
+use game::fingerprint::{check_game_build, image_range};
+use log::{debug, error, warn};
 use pocket_relay_client_shared as core;
-use std::path::Path;
+use std::{net::Ipv4Addr, path::Path};
 use ui::{confirm_message, error_message};
 use windows_sys::Win32::System::SystemServices::{DLL_PROCESS_ATTACH, DLL_PROCESS_DETACH};
 
+pub mod args;
 pub mod config;
+pub mod connection;
+pub mod diagnostics;
+pub mod event_file;
+pub mod event_log;
 pub mod game;
 pub mod hooks;
+pub mod hotkey;
+pub mod launcher;
+pub mod logging;
 pub mod servers;
+pub mod shutdown;
 pub mod threads;
 pub mod ui;
 pub mod update;
@@ -34,31 +45,151 @@ fn attach() {
     }
 
     // Initialize logging
-    env_logger::builder()
-        .filter_level(log::LevelFilter::Debug)
-        .init();
+    logging::init();
 
-    // Apply hooks
-    unsafe { hooks::apply_hooks() };
+    // Read the launcher arguments, these take precedence over the config
+    args::init();
 
     // Load the config file
     let config = read_config_file();
 
+    if config.as_ref().is_some_and(|config| config.trace_log) {
+        logging::enable_trace_log();
+    }
+
+    if config.as_ref().is_some_and(|config| config.event_log) {
+        event_log::init();
+        event_log::report_event(
+            EventKind::Information,
+            &format!("Pocket Relay plugin v{} loaded", APP_VERSION),
+        );
+    }
+
+    if config.as_ref().is_some_and(|config| config.event_file) {
+        event_file::init();
+        event_file::write_event(event_file::Event::Loaded {
+            version: APP_VERSION,
+        });
+    }
+
+    if config.as_ref().is_some_and(|config| config.trace_events) {
+        debug!("Game event tracing is enabled");
+        hooks::process_event::set_trace_events(true);
+    }
+
+    if let Some(address) = config
+        .as_ref()
+        .and_then(|config| config.redirect_address.as_ref())
+    {
+        match address.parse::<Ipv4Addr>() {
+            Ok(address) => {
+                debug!("Redirecting host lookups to {}", address);
+
+                // The redirector instance response always points at loopback
+                if !address.is_loopback() {
+                    warn!(
+                        "Redirect address {} is not loopback, the game will still connect \
+                        to the blaze server on 127.0.0.1:{} after the redirector",
+                        address,
+                        core::servers::BLAZE_PORT
+                    );
+                }
+
+                unsafe { hooks::host_lookup::set_redirect_address(address) };
+            }
+            Err(err) => error!("Invalid redirect address {:?}: {}", address, err),
+        }
+    }
+
+    if let Some(advanced) = config.as_ref().and_then(|config| config.advanced.as_ref()) {
+        apply_address_overrides(advanced);
+    }
+
+    // Hooks can only be applied to a supported game build
+    let expected_build = config.as_ref().and_then(|config| config.game_fingerprint);
+    match check_game_build(expected_build) {
+        Ok(fingerprint) => {
+            debug!("Detected game build: {}", fingerprint);
+
+            // Apply hooks
+            unsafe { hooks::apply_hooks() };
+        }
+        Err(err) => {
+            error!("Unsupported game version, hooks disabled: {}", err);
+            error_message(
+                "Unsupported game version",
+                &format!(
+                    "{}\n\nThe Pocket Relay hooks have been disabled, \
+                    you will not be able to play on Pocket Relay servers",
+                    err
+                ),
+            );
+        }
+    }
+
+    // Offsets are also dumped for unsupported builds to help with porting
+    if config.as_ref().is_some_and(|config| config.dump_offsets) {
+        diagnostics::dump_offsets();
+    }
+
     // Load the client identity if one is present
-    let identity = load_identity();
+    let identity = load_identity(
+        config
+            .as_ref()
+            .is_some_and(|config| config.always_use_identity),
+    );
 
-    // Create the internal HTTP client
-    let client: Client = create_http_client(identity).expect("Failed to create HTTP client");
+    // Create the internal HTTP clients
+    servers::init_lookup_client(identity.clone()).expect("Failed to create HTTP client");
+    let client: Client =
+        servers::create_http_client(identity).expect("Failed to create HTTP client");
 
     std::thread::spawn(|| {
         // Initialize the UI
-        ui::init(config, client);
+        let result = std::thread::spawn(|| {
+            // Dropped last, after the UI and runtime have stopped
+            let _stopped = shutdown::StoppedGuard;
+            ui::init(config, client)
+        })
+        .join();
+
+        // UI thread panicked, the networking is no longer usable
+        if result.is_err() {
+            handle_ui_crash();
+        }
     });
 }
 
+/// Handles the UI thread panicking, stops the servers and resumes the
+/// game so the user isn't left with a half connected game and no UI
+fn handle_ui_crash() {
+    error!("UI thread stopped unexpectedly");
+
+    // Servers can't be controlled without the UI
+    servers::stop_all_servers();
+
+    // Game threads may still be suspended
+    threads::resume_all_threads();
+
+    error_message(
+        "Pocket Relay stopped unexpectedly",
+        "The Pocket Relay client encountered an error and has stopped, the game \
+        will continue without Pocket Relay. Restart the game to connect again",
+    );
+}
+
 /// Handles the plugin being detached from the game, this handles
 /// cleaning up any extra allocated resources
-fn detach() {
+///
+/// ## Arguments
+/// * `process_exit` - Whether the game is exiting rather than unloading the plugin
+fn detach(process_exit: bool) {
+    // Other threads have already been terminated when the game is exiting,
+    // when only the plugin is unloaded they must be stopped first
+    if !process_exit {
+        shutdown::shutdown();
+    }
+
     // Debug console must be freed on detach
     #[cfg(debug_assertions)]
     {
@@ -68,13 +199,120 @@ fn detach() {
     }
 }
 
+/// Applies the manual address overrides from the `advanced` config section,
+/// invalid addresses and addresses outside the game image are ignored and
+/// the defaults are used instead
+///
+/// ## Arguments
+/// * `advanced` - The advanced config
+fn apply_address_overrides(advanced: &AdvancedConfig) {
+    let image = image_range();
+
+    // Each override is parsed once, addresses outside the game image are
+    // rejected as they can't be the hooked code or data. Exclusive `end`
+    // addresses may be the end of the image
+    let parse = |name: &str, value: Option<&String>, end: bool| -> Option<usize> {
+        let value = value?;
+        let Some(address) = parse_address(value) else {
+            error!("Invalid {} override {:?}, using default", name, value);
+            return None;
+        };
+
+        let Some(image) = &image else {
+            error!(
+                "Rejected {} override {:#010x}, the game image range couldn't be read",
+                name, address
+            );
+            return None;
+        };
+
+        let in_image = image.contains(&address) || (end && address == image.end);
+        if !in_image {
+            error!(
+                "Rejected {} override {:#010x}, outside the game image {:#010x}..{:#010x}",
+                name, address, image.start, image.end
+            );
+            return None;
+        }
+
+        warn!("MANUAL OVERRIDE IN USE: {} = {:#010x}", name, address);
+        Some(address)
+    };
+
+    let game_object_offset = parse(
+        "game_object_offset",
+        advanced.game_object_offset.as_ref(),
+        false,
+    );
+    let process_event_offset = parse(
+        "process_event_offset",
+        advanced.process_event_offset.as_ref(),
+        false,
+    );
+    let scan_start = parse("scan_start", advanced.scan_start.as_ref(), false);
+    let scan_end = parse("scan_end", advanced.scan_end.as_ref(), true);
+    let host_lookup_address = parse(
+        "host_lookup_address",
+        advanced.host_lookup_address.as_ref(),
+        false,
+    );
+
+    if let Some(offset) = game_object_offset {
+        game::core::set_game_object_offset(offset);
+    }
+
+    if let Some(offset) = process_event_offset {
+        hooks::process_event::set_process_event_offset(offset);
+    }
+
+    if scan_start.is_some() || scan_end.is_some() {
+        let (default_start, default_end) = hooks::host_lookup::scan_range();
+        let start = scan_start.unwrap_or(default_start);
+        let end = scan_end.unwrap_or(default_end);
+
+        if start < end {
+            hooks::host_lookup::set_scan_range(start, end);
+        } else {
+            error!(
+                "Invalid scan range override {:#x}..{:#x}, using default",
+                start, end
+            );
+        }
+    }
+
+    if let Some(address) = host_lookup_address {
+        hooks::host_lookup::set_call_address(address);
+    }
+
+    let applied = [
+        game_object_offset,
+        process_event_offset,
+        scan_start,
+        scan_end,
+        host_lookup_address,
+    ]
+    .iter()
+    .any(Option::is_some);
+
+    if applied {
+        warn!("Manual address overrides are in use, hooks may crash the game if they are wrong");
+    }
+}
+
 /// Attempts to load an identity file if one is present
-fn load_identity() -> Option<Identity> {
+///
+/// ## Arguments
+/// * `always_use` - Whether to use the identity without asking the user
+fn load_identity(always_use: bool) -> Option<Identity> {
     // Load the client identity
     let identity_file = Path::new("pocket-relay-identity.p12");
 
-    // Handle no identity or user declining identity
-    if !identity_file.exists() || !confirm_message(
+    if !identity_file.exists() {
+        return None;
+    }
+
+    // Handle user declining identity
+    if !always_use && !confirm_message(
         "Found client identity",
         "Detected client identity pocket-relay-identity.p12, would you like to use this identity?",
     ) {
@@ -95,12 +333,12 @@ fn load_identity() -> Option<Identity> {
 /// Windows DLL entrypoint for the plugin
 #[no_mangle]
 #[allow(non_snake_case)]
-extern "stdcall" fn DllMain(_hmodule: isize, reason: u32, _: *mut ()) -> bool {
+extern "stdcall" fn DllMain(_hmodule: isize, reason: u32, reserved: *mut ()) -> bool {
     match reason {
         // Handle attaching
         DLL_PROCESS_ATTACH => attach(),
-        // Handle detaching
-        DLL_PROCESS_DETACH => detach(),
+        // Handle detaching, reserved is non-null when the process is exiting
+        DLL_PROCESS_DETACH => detach(!reserved.is_null()),
         _ => {}
     }
 
@@ -0,0 +1,113 @@
+//! Logging setup, logs are written to the console with an optional
+//! separate trace level log file for diagnosing networking issues
+
+use crate::config::with_fallback_path;
+use env_logger::Logger;
+use log::{warn, LevelFilter, Log, Metadata, Record};
+use std::{
+    fs::File,
+    io::{LineWriter, Write},
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Name of the trace log file
+pub const TRACE_LOG_FILE_NAME: &str = "pocket-relay-trace.log";
+
+/// Trace log file, only set when trace logging is enabled
+static TRACE_LOG: OnceLock<Mutex<LineWriter<File>>> = OnceLock::new();
+
+/// Logger writing to the console logger and the trace log file
+struct PluginLogger {
+    /// Logger for the console output
+    console: Logger,
+}
+
+impl Log for PluginLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.console.enabled(metadata) || TRACE_LOG.get().is_some()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.console.matches(record) {
+            self.console.log(record);
+        }
+
+        let Some(trace_log) = TRACE_LOG.get() else {
+            return;
+        };
+
+        let Ok(mut trace_log) = trace_log.lock() else {
+            return;
+        };
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        // Failing to write the trace log isn't reported as that would log
+        let _ = writeln!(
+            trace_log,
+            "[{}.{:03} {} {}] {}",
+            timestamp.as_secs(),
+            timestamp.subsec_millis(),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+    }
+
+    fn flush(&self) {
+        self.console.flush();
+
+        if let Some(Ok(mut trace_log)) = TRACE_LOG.get().map(Mutex::lock) {
+            let _ = trace_log.flush();
+        }
+    }
+}
+
+/// Initializes logging, only debug level logs are written until
+/// [enable_trace_log] is called
+pub fn init() {
+    let console = env_logger::builder()
+        .filter_level(LevelFilter::Debug)
+        .build();
+    let max_level = console.filter();
+
+    if log::set_boxed_logger(Box::new(PluginLogger { console })).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/// Enables writing trace level logs to [TRACE_LOG_FILE_NAME] next to the
+/// config file, the fallback config directory is used when the game
+/// directory isn't writable. The console output is unchanged
+pub fn enable_trace_log() {
+    let Some((path, file)) = create_trace_log() else {
+        return;
+    };
+
+    if TRACE_LOG.set(Mutex::new(LineWriter::new(file))).is_err() {
+        return;
+    }
+
+    log::set_max_level(LevelFilter::Trace);
+
+    warn!(
+        "Trace logging is enabled, this writes a large amount of logs to: {}",
+        path.display()
+    );
+}
+
+/// Creates the trace log file replacing the log from the previous
+/// run, provides the path and file if it could be created
+fn create_trace_log() -> Option<(PathBuf, File)> {
+    match with_fallback_path(TRACE_LOG_FILE_NAME, |path| File::create(path)) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            warn!("Failed to create trace log: {}", err);
+            None
+        }
+    }
+}
@@ -1,17 +1,973 @@
 use crate::{
-    core::{ctx::ClientContext, servers::*},
+    config::{ClientConfig, ConnectionProfile, TunnelMode},
+    connection::mark_connection_active,
+    core::{
+        api::{
+            lookup_server, read_client_identity, LookupData, LookupError, DETAILS_ENDPOINT,
+            USER_AGENT,
+        },
+        ctx::ClientContext,
+        reqwest::{self, redirect::Policy, Client, Identity, Proxy, StatusCode},
+        servers::*,
+        Url, Version, MIN_SERVER_VERSION,
+    },
+    diagnostics::{record_error, ErrorCategory},
+    event_file::{write_event, Event},
+    event_log::{report_event, EventKind},
+    hooks::{
+        host_lookup::{redirect_stats, reset_redirect_stats, set_force_offline},
+        process_event::{set_version_warning, set_welcome_message},
+    },
     ui::error_message,
 };
-use log::error;
-use std::{future::Future, sync::Arc};
+use log::{debug, error, warn};
+use native_windows_gui::NoticeSender;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt::Display,
+    future::Future,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::Path,
+    ptr::addr_of,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+use tokio::net::{lookup_host, UdpSocket};
+use windows_sys::Win32::{
+    Foundation::{ERROR_INSUFFICIENT_BUFFER, FALSE, NO_ERROR},
+    NetworkManagement::IpHelper::{
+        GetExtendedTcpTable, MIB_TCPROW_OWNER_PID, MIB_TCPTABLE_OWNER_PID, MIB_TCP_STATE_ESTAB,
+        TCP_TABLE_CLASS, TCP_TABLE_OWNER_PID_ALL, TCP_TABLE_OWNER_PID_LISTENER,
+    },
+    Networking::WinSock::AF_INET,
+};
+
+/// Interval between latency probes while connected
+const LATENCY_PROBE_INTERVAL: Duration = Duration::from_secs(15);
+/// Maximum time to wait for a latency probe response
+const LATENCY_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Value stored in [LATENCY] when the latency is unknown
+const LATENCY_UNKNOWN: u32 = u32::MAX;
+
+/// Latest measured latency to the connected server in milliseconds
+static LATENCY: AtomicU32 = AtomicU32::new(LATENCY_UNKNOWN);
+
+/// Maximum time to wait for the tunnel host lookup when checking the
+/// UDP tunnel can be used
+const TUNNEL_CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Time that successful lookups are cached for
+const LOOKUP_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// Cache of successful lookups keyed by the normalized connection target,
+/// used to skip the lookup when reconnecting to the same server
+static LOOKUP_CACHE: Mutex<BTreeMap<String, CachedLookup>> = Mutex::new(BTreeMap::new());
+
+/// Successful lookup stored in the [LOOKUP_CACHE]
+struct CachedLookup {
+    /// When the lookup was made
+    created: Instant,
+    /// The lookup details
+    lookup: LookupData,
+    /// Whether the lookup is for a trusted profile, trusted lookups
+    /// don't expire and are kept until a connection fails
+    trusted: bool,
+}
+
+/// Scheme used by deep links shared by server operators
+const DEEP_LINK_SCHEME: &str = "pocketrelay://";
+
+/// Characters that chat apps and users commonly wrap copied URLs with
+const WRAPPING_CHARS: &[char] = &['<', '>', '"', '\'', '`', '(', ')'];
+/// Punctuation commonly copied along with the end of a URL
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', '!', '?'];
+
+/// Removes the formatting commonly copied along with a URL from chat apps,
+/// markdown links (`[text](url)`), surrounding quotes and angle brackets,
+/// trailing punctuation and repeated trailing slashes. Protocol relative
+/// URLs (`//host`) have the leading slashes removed so the scheme is
+/// inferred by the lookup
+///
+/// ## Arguments
+/// * `target` - The connection target to sanitize
+fn sanitize_target(target: &str) -> &str {
+    // Punctuation may follow the closing bracket or quote
+    let mut target = target.trim().trim_end_matches(TRAILING_PUNCTUATION);
+
+    // Markdown links use the link URL
+    if let Some(url) = target
+        .strip_prefix('[')
+        .and_then(|value| value.strip_suffix(')'))
+        .and_then(|value| value.split_once("]("))
+        .map(|(_, url)| url)
+    {
+        target = url;
+    }
+
+    let target = target
+        .trim_matches(|c: char| c.is_whitespace() || WRAPPING_CHARS.contains(&c))
+        .trim_end_matches(TRAILING_PUNCTUATION);
+    let target = target.strip_prefix("//").unwrap_or(target);
+
+    // Collapse repeated trailing slashes into one, slashes directly after
+    // the scheme are kept
+    let trimmed = target.trim_end_matches('/');
+    if target.len() - trimmed.len() > 1 && !trimmed.ends_with(':') {
+        &target[..trimmed.len() + 1]
+    } else {
+        target
+    }
+}
+
+/// Normalizes a connection target, converting `pocketrelay://host` style
+/// deep links into a `http://host` URL that can be used for lookup. Links
+/// that wrap a full URL (`pocketrelay://https://host`) use the wrapped URL.
+/// Other targets are returned sanitized using [sanitize_target] but
+/// otherwise unchanged
+///
+/// ## Arguments
+/// * `target` - The connection target to normalize
+pub fn normalize_target(target: &str) -> String {
+    let target = sanitize_target(target);
+
+    let scheme = target.get(..DEEP_LINK_SCHEME.len());
+    if !scheme.is_some_and(|scheme| scheme.eq_ignore_ascii_case(DEEP_LINK_SCHEME)) {
+        return target.to_string();
+    }
+
+    let host = target[DEEP_LINK_SCHEME.len()..].trim_end_matches('/');
+    if host.starts_with("http://") || host.starts_with("https://") {
+        host.to_string()
+    } else {
+        format!("http://{host}")
+    }
+}
+
+/// Parses a minimum server version entered by the user, a leading "v"
+/// is allowed. Provides [None] if the version isn't valid semver
+///
+/// ## Arguments
+/// * `value` - The version to parse
+pub fn parse_min_server_version(value: &str) -> Option<Version> {
+    Version::parse(value.trim().trim_start_matches('v')).ok()
+}
+
+/// Provides the minimum server version override from the config, the
+/// override is only used when it is higher than the [MIN_SERVER_VERSION]
+/// already required by the client. Invalid versions are ignored
+///
+/// ## Arguments
+/// * `config` - The client config
+pub fn min_server_version_override(config: &ClientConfig) -> Option<Version> {
+    let value = config.min_server_version.as_deref()?;
+
+    let Some(version) = parse_min_server_version(value) else {
+        warn!("Ignoring invalid minimum server version: {}", value);
+        return None;
+    };
+
+    Some(version).filter(|version| *version > MIN_SERVER_VERSION)
+}
+
+/// Provides the effective minimum server version, the higher of the
+/// [MIN_SERVER_VERSION] and the override from the config
+///
+/// ## Arguments
+/// * `config` - The client config
+pub fn effective_min_server_version(config: &ClientConfig) -> Version {
+    min_server_version_override(config).unwrap_or(MIN_SERVER_VERSION)
+}
+
+/// Options used when connecting to a server
+#[derive(Debug, Clone, Default)]
+pub struct ConnectOptions {
+    /// The tunnel mode to use
+    pub tunnel_mode: TunnelMode,
+    /// Hosts that are allowed to be connected to, any host is
+    /// allowed when empty
+    pub allowed_hosts: Vec<String>,
+    /// Connection profiles with their own client settings
+    pub profiles: Vec<ConnectionProfile>,
+    /// Retry failed HTTPS lookups to loopback servers over HTTP
+    pub loopback_http_fallback: bool,
+    /// Minimum server version to allow connecting to when higher
+    /// than the version required by the client
+    pub min_server_version: Option<Version>,
+    /// Wait for the local servers to be listening before completing
+    /// the connection
+    pub wait_for_servers: bool,
+}
+
+impl ConnectOptions {
+    /// Creates the connect options from the client config
+    ///
+    /// ## Arguments
+    /// * `config` - The client config
+    pub fn from_config(config: &ClientConfig) -> Self {
+        Self {
+            tunnel_mode: config.tunnel_mode,
+            allowed_hosts: config.allowed_hosts.clone(),
+            profiles: config.profiles.clone(),
+            loopback_http_fallback: config.loopback_http_fallback,
+            min_server_version: min_server_version_override(config),
+            wait_for_servers: config.wait_for_servers,
+        }
+    }
+
+    /// Finds the profile for the provided `target`, the target can
+    /// either be the profile name or its connection URL
+    ///
+    /// ## Arguments
+    /// * `target` - The connection target
+    pub fn find_profile(&self, target: &str) -> Option<&ConnectionProfile> {
+        self.profiles.iter().find(|profile| {
+            profile.name.eq_ignore_ascii_case(target)
+                || normalize_target(&profile.connection_url) == target
+        })
+    }
+
+    /// Checks whether the host of the provided `target` is allowed
+    ///
+    /// ## Arguments
+    /// * `target` - The connection target
+    pub fn is_host_allowed(&self, target: &str) -> bool {
+        if self.allowed_hosts.is_empty() {
+            return true;
+        }
+
+        let Some(host) = target_host(target) else {
+            return false;
+        };
+
+        self.allowed_hosts.iter().any(|allowed| {
+            target_host(allowed).is_some_and(|allowed| allowed.eq_ignore_ascii_case(&host))
+        })
+    }
+}
+
+/// Optional banner and maintenance details provided by the server
+/// details endpoint, servers that don't provide these use the defaults
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ServerBanner {
+    /// Message to show to connecting players
+    #[serde(default)]
+    pub banner: Option<String>,
+    /// Maintenance details when the server is under maintenance
+    #[serde(default)]
+    pub maintenance: Option<MaintenanceDetails>,
+    /// Message of the day shown once in the in-game terminal
+    #[serde(default)]
+    pub motd: Option<String>,
+}
+
+/// Details about server maintenance
+#[derive(Debug, Clone, Deserialize)]
+pub struct MaintenanceDetails {
+    /// Message describing the maintenance
+    pub message: String,
+    /// Whether connections should be refused during the maintenance
+    #[serde(default)]
+    pub block: bool,
+}
+
+impl ServerBanner {
+    /// Creates the message to show the user from the banner and
+    /// maintenance details, [None] if there is nothing to show
+    pub fn message(&self) -> Option<String> {
+        let maintenance = self
+            .maintenance
+            .as_ref()
+            .map(|maintenance| format!("Server maintenance: {}", maintenance.message));
+
+        let parts: Vec<String> = [self.banner.clone(), maintenance]
+            .into_iter()
+            .flatten()
+            .collect();
+
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join("\n\n"))
+        }
+    }
+}
+
+/// Requests the banner details from the server details endpoint, failing
+/// to load the banner is not an error as most servers won't provide one
+///
+/// ## Arguments
+/// * `http_client` - The HTTP client to use
+/// * `base_url`    - The base URL of the server
+async fn fetch_server_banner(http_client: &Client, base_url: &Url) -> ServerBanner {
+    let Ok(url) = base_url.join(DETAILS_ENDPOINT) else {
+        return ServerBanner::default();
+    };
+
+    let response = match http_client.get(url).send().await {
+        Ok(value) => value,
+        Err(err) => {
+            debug!("Failed to request server banner: {}", err);
+            return ServerBanner::default();
+        }
+    };
+
+    match response.json().await {
+        Ok(value) => value,
+        Err(err) => {
+            debug!("Failed to parse server banner: {}", err);
+            ServerBanner::default()
+        }
+    }
+}
+
+/// Details about a server that was connected to
+#[derive(Debug)]
+pub struct ConnectedServer {
+    /// The server lookup details
+    pub lookup: LookupData,
+    /// Banner provided by the server
+    pub banner: ServerBanner,
+}
+
+/// Errors that can occur while connecting
+#[derive(Debug)]
+pub enum ConnectError {
+    /// The host is not in the allowed hosts list
+    HostNotAllowed(String),
+    /// The server is refusing connections for maintenance
+    Maintenance(String),
+    /// Failed to create the HTTP client for a connection profile
+    Profile(String),
+    /// The server responded with content that wasn't the expected JSON
+    /// (HTML login page), likely a captive portal or proxy intercepting
+    /// the request
+    UnexpectedContent,
+    /// The target is one of the local servers started by the plugin
+    LocalServer(u16),
+    /// A local server didn't start listening in time
+    ServerNotReady(&'static str),
+    /// Failed to lookup the server
+    Lookup(LookupError),
+}
+
+impl Display for ConnectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectError::HostNotAllowed(target) => write!(
+                f,
+                "Connecting to {} is not allowed, only the servers allowed in the config can be used",
+                target
+            ),
+            ConnectError::Profile(err) => write!(f, "Failed to load connection profile: {}", err),
+            ConnectError::ServerNotReady(name) => write!(
+                f,
+                "The local {} server didn't start in time, another program may be using \
+                its port. Try connecting again",
+                name
+            ),
+            ConnectError::LocalServer(port) => write!(
+                f,
+                "Port {} on this computer is used by the Pocket Relay client itself, \
+                enter the connection URL of the actual server instead",
+                port
+            ),
+            ConnectError::Maintenance(message) => write!(
+                f,
+                "The server is currently under maintenance and isn't accepting connections: {}",
+                message
+            ),
+            ConnectError::UnexpectedContent => f.write_str(
+                "The server responded with a web page instead of the expected server details. \
+                Your network may require you to sign in through a web browser (captive portal) \
+                or a proxy may be intercepting the connection",
+            ),
+            ConnectError::Lookup(err) => err.fmt(f),
+        }
+    }
+}
+
+impl From<LookupError> for ConnectError {
+    fn from(value: LookupError) -> Self {
+        ConnectError::Lookup(value)
+    }
+}
+
+/// Maximum number of redirects followed for a single request
+const MAX_REDIRECTS: usize = 5;
+
+/// Creates the redirect policy for the server lookup. Redirects are only
+/// followed when they stay on the same host (e.g. http to https upgrades) so a
+/// redirect can't hand the connection to an entirely different server. The
+/// resolved [LookupData::url] is the original connection URL, later requests
+/// rely on the server redirecting them the same way
+fn server_redirect_policy() -> Policy {
+    Policy::custom(
+        |attempt| match check_redirect(attempt.previous(), attempt.url()) {
+            Ok(()) => attempt.follow(),
+            Err(message) => attempt.error(message),
+        },
+    )
+}
+
+/// Decides whether a redirect to `next` should be followed for the
+/// [server_redirect_policy], provides the error message when it shouldn't
+///
+/// ## Arguments
+/// * `previous` - The original URL followed by the URLs already redirected to
+/// * `next`     - The URL being redirected to
+fn check_redirect(previous: &[Url], next: &Url) -> Result<(), String> {
+    // Previous URLs include the original request URL
+    if previous.len() > MAX_REDIRECTS {
+        return Err(format!("Too many redirects (limit {MAX_REDIRECTS})"));
+    }
+
+    let original_host = previous.first().and_then(|url| url.host_str());
+    let host = next.host_str();
+
+    if original_host != host {
+        return Err(format!(
+            "Server redirected to a different host ({}), connect using that address instead",
+            host.unwrap_or("unknown")
+        ));
+    }
+
+    Ok(())
+}
+
+/// Client used for the server lookup, uses the [server_redirect_policy].
+/// Set by [init_lookup_client]
+static LOOKUP_CLIENT: OnceLock<Client> = OnceLock::new();
+
+/// Creates the default HTTP client used for communicating with servers, the
+/// client is also used by the updater and the local HTTP proxy so it keeps
+/// the default redirect policy (Release downloads redirect to another host)
+///
+/// ## Arguments
+/// * `identity` - Optional identity for the client to use
+pub fn create_http_client(identity: Option<Identity>) -> Result<Client, reqwest::Error> {
+    let mut builder = Client::builder().user_agent(USER_AGENT);
+
+    if let Some(identity) = identity {
+        builder = builder.identity(identity);
+    }
+
+    builder.build()
+}
+
+/// Creates the client used for the server lookup, which only follows
+/// redirects allowed by the [server_redirect_policy]
+///
+/// ## Arguments
+/// * `identity` - Optional identity for the client to use
+pub fn init_lookup_client(identity: Option<Identity>) -> Result<(), reqwest::Error> {
+    let mut builder = Client::builder()
+        .user_agent(USER_AGENT)
+        .redirect(server_redirect_policy());
+
+    if let Some(identity) = identity {
+        builder = builder.identity(identity);
+    }
+
+    let _ = LOOKUP_CLIENT.set(builder.build()?);
+    Ok(())
+}
+
+/// Provides the client used for the server lookup, the `default_client`
+/// is used if [init_lookup_client] hasn't been called
+///
+/// ## Arguments
+/// * `default_client` - The default HTTP client
+pub fn lookup_client(default_client: &Client) -> Client {
+    LOOKUP_CLIENT
+        .get()
+        .cloned()
+        .unwrap_or_else(|| default_client.clone())
+}
+
+/// HTTP clients used when connecting with a connection profile
+struct ProfileClients {
+    /// Client used for the server lookup
+    lookup: Client,
+    /// Client used by the local servers once connected
+    http: Client,
+}
+
+/// Creates the HTTP clients to use for a connection `profile`, profiles
+/// without an identity or proxy use the `default_client` and the default
+/// lookup client
+///
+/// ## Arguments
+/// * `profile`        - The connection profile
+/// * `default_client` - The client to use when the profile doesn't need its own
+fn create_profile_clients(
+    profile: &ConnectionProfile,
+    default_client: Client,
+) -> Result<ProfileClients, String> {
+    if profile.identity_path.is_none() && profile.outbound_proxy.is_none() {
+        return Ok(ProfileClients {
+            lookup: lookup_client(&default_client),
+            http: default_client,
+        });
+    }
+
+    let identity = match &profile.identity_path {
+        Some(identity_path) => {
+            Some(read_client_identity(Path::new(identity_path)).map_err(|err| err.to_string())?)
+        }
+        None => None,
+    };
+
+    let proxy = match &profile.outbound_proxy {
+        Some(outbound_proxy) => Some(Proxy::all(outbound_proxy).map_err(|err| err.to_string())?),
+        None => None,
+    };
+
+    let build = |policy: Policy| {
+        let mut builder = Client::builder().user_agent(USER_AGENT).redirect(policy);
+
+        if let Some(identity) = identity.clone() {
+            builder = builder.identity(identity);
+        }
+
+        if let Some(proxy) = proxy.clone() {
+            builder = builder.proxy(proxy);
+        }
+
+        builder.build().map_err(|err| err.to_string())
+    };
+
+    Ok(ProfileClients {
+        lookup: build(server_redirect_policy())?,
+        http: build(Policy::default())?,
+    })
+}
+
+/// Provides the host of a connection `target`, targets without
+/// a scheme are treated as http URLs
+///
+/// ## Arguments
+/// * `target` - The connection target
+fn target_host(target: &str) -> Option<String> {
+    let target = target.trim();
+    let url = if target.starts_with("http://") || target.starts_with("https://") {
+        Url::parse(target)
+    } else {
+        Url::parse(&format!("http://{target}"))
+    };
+
+    url.ok()?.host_str().map(str::to_string)
+}
+
+/// Ports bound by the local servers started when connected
+const LOCAL_SERVER_PORTS: [u16; 6] = [
+    REDIRECTOR_PORT,
+    BLAZE_PORT,
+    TELEMETRY_PORT,
+    QOS_PORT,
+    HTTP_PORT,
+    TUNNEL_HOST_PORT,
+];
+
+/// Checks whether the connection `target` points at one of the local servers
+/// started by the plugin, provides the port if it does. The lookup URL uses
+/// the same host and port as the target so the target is checked before the
+/// lookup to avoid the lookup request reaching the local servers
+///
+/// ## Arguments
+/// * `target` - The connection target
+fn local_server_port(target: &str) -> Option<u16> {
+    let target = target.trim();
+    let url = if target.starts_with("http://") || target.starts_with("https://") {
+        Url::parse(target)
+    } else {
+        Url::parse(&format!("http://{target}"))
+    }
+    .ok()?;
+
+    url_local_server_port(&url)
+}
+
+/// Checks whether the `url` points at one of the local servers started by
+/// the plugin, provides the port if it does. Used for the server URL from
+/// the lookup which may differ from the connection target
+///
+/// ## Arguments
+/// * `url` - The URL to check
+fn url_local_server_port(url: &Url) -> Option<u16> {
+    let host = url
+        .host_str()?
+        // IPv6 hosts are wrapped in brackets
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+
+    let is_loopback = host.eq_ignore_ascii_case("localhost")
+        || host.parse::<IpAddr>().is_ok_and(|addr| addr.is_loopback());
+
+    let port = url.port_or_known_default()?;
+
+    (is_loopback && LOCAL_SERVER_PORTS.contains(&port)).then_some(port)
+}
+
+/// Looks up the server at the provided `target` and starts all the
+/// servers using the resolved server details. Lookups are reused for
+/// reconnects within [LOOKUP_CACHE_TTL], or for the rest of the session
+/// for trusted profiles. Servers under maintenance that block connections
+/// are not connected to
+///
+/// ## Arguments
+/// * `http_client` - The HTTP client to use
+/// * `target`      - The connection URL of the server
+/// * `options`     - The options to connect with
+pub async fn connect(
+    http_client: Client,
+    target: String,
+    options: ConnectOptions,
+) -> Result<ConnectedServer, ConnectError> {
+    // Profiles use their own connection URL and HTTP clients
+    let (target, clients, trusted) = match options.find_profile(&target) {
+        Some(profile) => {
+            debug!("Using connection profile {}", profile.name);
+            let clients =
+                create_profile_clients(profile, http_client).map_err(ConnectError::Profile)?;
+            (
+                normalize_target(&profile.connection_url),
+                clients,
+                profile.trusted,
+            )
+        }
+        None => {
+            let clients = ProfileClients {
+                lookup: lookup_client(&http_client),
+                http: http_client,
+            };
+            (target, clients, false)
+        }
+    };
+
+    if !options.is_host_allowed(&target) {
+        return Err(ConnectError::HostNotAllowed(target));
+    }
+
+    // Connecting to the local servers would make them proxy to themselves
+    if let Some(port) = local_server_port(&target) {
+        return Err(ConnectError::LocalServer(port));
+    }
+
+    // Connecting leaves offline mode
+    set_force_offline(false);
+
+    let mut lookup = match cached_lookup(&target) {
+        Some(value) => {
+            debug!("Using cached lookup for {}", target);
+            value
+        }
+        None => {
+            let lookup = match lookup_target(&clients.lookup, &target, &options).await {
+                Ok(value) => value,
+                Err(err) => {
+                    clear_lookup_cache();
+                    return Err(err);
+                }
+            };
+
+            cache_lookup(target, lookup.clone(), trusted);
+            lookup
+        }
+    };
+
+    // Server may direct the client to the local servers after the lookup
+    if let Some(port) = url_local_server_port(&lookup.url) {
+        clear_lookup_cache();
+        return Err(ConnectError::LocalServer(port));
+    }
+
+    // The client only enforces its own minimum version during lookup
+    if let Some(min_version) = options
+        .min_server_version
+        .filter(|min_version| lookup.version < *min_version)
+    {
+        clear_lookup_cache();
+        return Err(ConnectError::Lookup(LookupError::ServerOutdated(
+            lookup.version,
+            min_version,
+        )));
+    }
+
+    let banner = fetch_server_banner(&clients.lookup, &lookup.url).await;
+    if let Some(maintenance) = banner
+        .maintenance
+        .as_ref()
+        .filter(|maintenance| maintenance.block)
+    {
+        return Err(ConnectError::Maintenance(maintenance.message.clone()));
+    }
+
+    let ctx = Arc::new(ClientContext {
+        http_client: clients.http,
+        base_url: lookup.url.clone(),
+        association: lookup.association.take(),
+        tunnel_port: lookup.tunnel_port,
+    });
+
+    // Start the servers
+    start_all_servers(ctx, options.tunnel_mode);
+
+    // Servers are stopped if the wait fails or the connection is cancelled
+    let guard = StartedServersGuard::new();
+
+    // The game must not continue until it can reach the servers
+    if options.wait_for_servers {
+        if let Err(name) = wait_for_servers_ready().await {
+            return Err(ConnectError::ServerNotReady(name));
+        }
+    }
+
+    guard.disarm();
+
+    // Greet the player in the game with the server message of the day
+    set_welcome_message(banner.motd.clone());
+
+    report_event(
+        EventKind::Information,
+        &format!("Connected to server {} (v{})", lookup.url, lookup.version),
+    );
+    write_event(Event::Connected {
+        url: lookup.url.as_str(),
+        version: lookup.version.to_string(),
+    });
+
+    Ok(ConnectedServer { lookup, banner })
+}
+
+/// Looks up the server at `target`. Loopback servers that fail to connect
+/// over HTTPS are retried over HTTP when enabled, as local servers are
+/// commonly plain HTTP. Remote servers are never downgraded
+///
+/// ## Arguments
+/// * `http_client` - The HTTP client to use
+/// * `target`      - The connection target
+/// * `options`     - The connection options
+pub async fn lookup_target(
+    http_client: &Client,
+    target: &str,
+    options: &ConnectOptions,
+) -> Result<LookupData, ConnectError> {
+    let err = match lookup_server_details(http_client, target).await {
+        Ok(value) => return Ok(value),
+        Err(err) => err,
+    };
+
+    if let ConnectError::Lookup(LookupError::ConnectionFailed(_)) = &err {
+        let http_target = target
+            .strip_prefix("https://")
+            .filter(|_| options.loopback_http_fallback && is_loopback_target(target))
+            .map(|rest| format!("http://{rest}"));
+
+        if let Some(http_target) = http_target {
+            warn!(
+                "HTTPS lookup to loopback server failed ({}), retrying over HTTP: {}",
+                err, http_target
+            );
+
+            if let Ok(value) = lookup_server_details(http_client, &http_target).await {
+                return Ok(value);
+            }
+        }
+    }
+
+    Err(err)
+}
+
+/// Looks up the server details for `target` using the shared lookup, lookup
+/// failures caused by a response that clearly isn't the expected JSON (the
+/// HTML login page of a captive portal) are reported as
+/// [ConnectError::UnexpectedContent]
+///
+/// ## Arguments
+/// * `http_client` - The HTTP client to use
+/// * `target`      - The connection target
+async fn lookup_server_details(
+    http_client: &Client,
+    target: &str,
+) -> Result<LookupData, ConnectError> {
+    lookup_server(http_client.clone(), target.to_string())
+        .await
+        .map_err(|err| {
+            if is_unexpected_content(&err) {
+                ConnectError::UnexpectedContent
+            } else {
+                ConnectError::Lookup(err)
+            }
+        })
+}
+
+/// Checks whether a lookup `err` was caused by the response to the lookup
+/// being something other than the server details
+///
+/// ## Arguments
+/// * `err` - The lookup error
+fn is_unexpected_content(err: &LookupError) -> bool {
+    match err {
+        // Network authentication required is only used by captive portals
+        LookupError::ErrorResponse(err) => {
+            err.status() == Some(StatusCode::NETWORK_AUTHENTICATION_REQUIRED)
+        }
+        // Decoding errors keep the JSON error for the response body
+        LookupError::InvalidResponse(err) => err
+            .source()
+            .and_then(|err| err.downcast_ref::<serde_json::Error>())
+            .is_some_and(is_non_json_error),
+        _ => false,
+    }
+}
+
+/// Checks whether a JSON parsing `err` was caused by the body not being
+/// JSON at all rather than JSON with the wrong structure, JSON bodies always
+/// start with a value so anything else fails on the first character
+///
+/// ## Arguments
+/// * `err` - The JSON parsing error
+fn is_non_json_error(err: &serde_json::Error) -> bool {
+    err.is_syntax() && err.to_string().starts_with("expected value")
+}
+
+/// Checks whether the host of `target` is a loopback address
+///
+/// ## Arguments
+/// * `target` - The connection target
+fn is_loopback_target(target: &str) -> bool {
+    let Some(host) = target_host(target) else {
+        return false;
+    };
+
+    if host.eq_ignore_ascii_case("localhost") {
+        return true;
+    }
+
+    // IPv6 hosts are wrapped in brackets within URLs
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+        .is_ok_and(|addr| addr.is_loopback())
+}
+
+/// Provides the cached lookup for `target` if one was made within
+/// the [LOOKUP_CACHE_TTL] or if the lookup is for a trusted profile
+///
+/// ## Arguments
+/// * `target` - The normalized connection target
+fn cached_lookup(target: &str) -> Option<LookupData> {
+    let mut cache = LOOKUP_CACHE.lock().ok()?;
+
+    // Remove expired entries
+    cache.retain(|_, cached| cached.trusted || cached.created.elapsed() < LOOKUP_CACHE_TTL);
+
+    cache.get(target).map(|cached| cached.lookup.clone())
+}
+
+/// Stores a successful `lookup` for the `target` in the [LOOKUP_CACHE]
+///
+/// ## Arguments
+/// * `target`  - The normalized connection target
+/// * `lookup`  - The lookup to store
+/// * `trusted` - Whether the lookup is kept for the rest of the session
+fn cache_lookup(target: String, lookup: LookupData, trusted: bool) {
+    if let Ok(mut cache) = LOOKUP_CACHE.lock() {
+        cache.insert(
+            target,
+            CachedLookup {
+                created: Instant::now(),
+                lookup,
+                trusted,
+            },
+        );
+    }
+}
+
+/// Clears all cached lookups, used when connecting fails so that a
+/// changed server isn't hidden by a stale lookup
+pub fn clear_lookup_cache() {
+    if let Ok(mut cache) = LOOKUP_CACHE.lock() {
+        cache.clear();
+    }
+}
+
+/// Number of times the servers have been started, used to tell whether the
+/// running servers were started by a specific connection attempt
+static SERVERS_STARTED: AtomicU32 = AtomicU32::new(0);
+
+/// Guard that stops the servers started by a connection attempt when the
+/// attempt fails or is cancelled (the connect future is dropped) before
+/// completing. Servers started by a later attempt are left running
+struct StartedServersGuard {
+    /// Value of [SERVERS_STARTED] when the guard was created
+    started: u32,
+    /// Whether the servers should be stopped on drop
+    armed: bool,
+}
+
+impl StartedServersGuard {
+    /// Creates a guard for the most recently started servers
+    fn new() -> Self {
+        Self {
+            started: SERVERS_STARTED.load(Ordering::Acquire),
+            armed: true,
+        }
+    }
+
+    /// Keeps the servers running once the connection has completed
+    fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for StartedServersGuard {
+    fn drop(&mut self) {
+        if self.armed && SERVERS_STARTED.load(Ordering::Acquire) == self.started {
+            debug!("Stopping servers from incomplete connection");
+            stop_all_servers();
+        }
+    }
+}
+
+/// Stops all the server tasks and resets the state kept for the current
+/// connection (redirect stats, latency and the pending server messages)
+/// so that the next connection starts from a clean state.
+///
+/// Lookups are deliberately kept in the cache, reconnecting after a
+/// disconnect is what the cache is for. Entries still expire after
+/// [LOOKUP_CACHE_TTL] (unless trusted) and the cache is cleared when a
+/// connection fails so a changed server is looked up again
+pub fn stop_all_servers() {
+    if has_server_tasks() {
+        write_event(Event::ConnectionStats {
+            redirects: redirect_stats().0,
+            latency_ms: latency(),
+        });
+    }
+
+    stop_server_tasks();
+    reset_redirect_stats();
+    LATENCY.store(LATENCY_UNKNOWN, Ordering::Release);
+    set_welcome_message(None);
+    set_version_warning(None);
+}
 
 /// Starts all the servers in their own tasks
 ///
 /// ## Arguments
-/// * `ctx` - The client context
-pub fn start_all_servers(ctx: Arc<ClientContext>) {
+/// * `ctx`         - The client context
+/// * `tunnel_mode` - The tunnel mode to use
+pub fn start_all_servers(ctx: Arc<ClientContext>, tunnel_mode: TunnelMode) {
     // Stop existing servers and tasks if they are running
-    stop_server_tasks();
+    stop_all_servers();
+
+    SERVERS_STARTED.fetch_add(1, Ordering::AcqRel);
 
     // Spawn redirector server
     let redirector = redirector::start_redirector_server();
@@ -30,7 +986,7 @@ pub fn start_all_servers(ctx: Arc<ClientContext>) {
     run_server(qos, "qos");
 
     // Spawn tunnel server
-    let tunnel = start_tunnel_server(ctx.clone());
+    let tunnel = start_tunnel_server(ctx.clone(), tunnel_mode);
     run_server(tunnel, "tunnel");
 
     // Spawn telemetry server
@@ -40,32 +996,243 @@ pub fn start_all_servers(ctx: Arc<ClientContext>) {
 
 /// Runs the tunnel server, if a tunnel port is available a UDP tunnel will be
 /// attempted, if that fails or a tunnel port is unavailable an HTTP tunnel
-/// will be attempted instead
-async fn start_tunnel_server(ctx: Arc<ClientContext>) -> std::io::Result<()> {
-    // Spawn tunnel server
-    match ctx.tunnel_port {
+/// will be attempted instead. The `tunnel_mode` can force the HTTP tunnel
+/// for networks that block the UDP tunnel port
+async fn start_tunnel_server(
+    ctx: Arc<ClientContext>,
+    tunnel_mode: TunnelMode,
+) -> std::io::Result<()> {
+    let tunnel_port = match (tunnel_mode, ctx.tunnel_port) {
+        // HTTP tunnel is forced by the config
+        (TunnelMode::Http, _) => {
+            debug!("Using HTTP tunnel (forced by config)");
+            return tunnel::start_tunnel_server(ctx).await;
+        }
+
         // When UDP tunnel server port is available use the faster UDP tunnel server
-        Some(tunnel_port) => {
-            let err = match udp_tunnel::start_udp_tunnel_server(ctx.clone(), tunnel_port).await {
-                // Encountered error with UDP tunnel
-                Err(err) => err,
-                // Server exited normally
-                Ok(_) => return Ok(()),
-            };
+        (_, Some(tunnel_port)) => tunnel_port,
 
-            error!(
-                "error using UDP tunnel, falling back to HTTP tunnel: {}",
-                err
+        // UDP tunnel is forced but the server didn't provide a port
+        (TunnelMode::Udp, None) => {
+            warn!("UDP tunnel requested but server has no tunnel port, using HTTP tunnel");
+            return tunnel::start_tunnel_server(ctx).await;
+        }
+
+        // When unavailable fallback to the HTTP upgrade tunnel
+        (TunnelMode::Auto, None) => {
+            debug!("Server has no tunnel port, using HTTP tunnel");
+            return tunnel::start_tunnel_server(ctx).await;
+        }
+    };
+
+    if let Err(reason) = check_udp_tunnel(&ctx, tunnel_port).await {
+        warn!(
+            "UDP tunnel port {} isn't usable ({}), using HTTP tunnel",
+            tunnel_port, reason
+        );
+        return tunnel::start_tunnel_server(ctx).await;
+    }
+
+    debug!("Using UDP tunnel (port {})", tunnel_port);
+
+    let err = match udp_tunnel::start_udp_tunnel_server(ctx.clone(), tunnel_port).await {
+        // Encountered error with UDP tunnel
+        Err(err) => err,
+        // Server exited normally
+        Ok(_) => return Ok(()),
+    };
+
+    error!(
+        "error using UDP tunnel, falling back to HTTP tunnel: {}",
+        err
+    );
+
+    // Error while connecting UDP tunnel, fallback to HTTP upgrade tunnel
+    tunnel::start_tunnel_server(ctx).await
+}
+
+/// Checks that the UDP tunnel can be used with the `tunnel_port` provided by
+/// the server. The port must be valid, the server host must resolve and be
+/// routable, and the server must have provided an association token. No
+/// data is sent to the server. Provides the reason when the tunnel can't
+/// be used
+///
+/// ## Arguments
+/// * `ctx`         - The client context
+/// * `tunnel_port` - The tunnel port provided by the server
+async fn check_udp_tunnel(ctx: &ClientContext, tunnel_port: u16) -> Result<(), String> {
+    if tunnel_port == 0 {
+        return Err("invalid port".to_string());
+    }
+
+    if ctx.association.is_none() {
+        return Err("server didn't provide an association token".to_string());
+    }
+
+    let host = ctx
+        .base_url
+        .host_str()
+        .ok_or_else(|| "server URL has no host".to_string())?
+        // IPv6 hosts are wrapped in brackets
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+
+    let addr = tokio::time::timeout(TUNNEL_CHECK_TIMEOUT, lookup_host((host, tunnel_port)))
+        .await
+        .map_err(|_| "host lookup timed out".to_string())?
+        .map_err(|err| format!("host lookup failed: {err}"))?
+        .next()
+        .ok_or_else(|| "host has no addresses".to_string())?;
+
+    // Connecting a UDP socket only checks that the address is routable
+    let bind_addr: SocketAddr = if addr.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+
+    let socket = UdpSocket::bind(bind_addr)
+        .await
+        .map_err(|err| format!("failed to bind socket: {err}"))?;
+
+    socket
+        .connect(addr)
+        .await
+        .map_err(|err| format!("{addr} is unreachable: {err}"))?;
+
+    debug!("UDP tunnel address {} is reachable", addr);
+
+    Ok(())
+}
+
+/// Local servers the game connects to directly, these must be listening
+/// before the game is allowed to continue
+const CRITICAL_SERVERS: [(u16, &str); 3] = [
+    (REDIRECTOR_PORT, "redirector"),
+    (BLAZE_PORT, "blaze"),
+    (HTTP_PORT, "http"),
+];
+
+/// Maximum time to wait for the local servers to start listening
+const SERVER_READY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Interval between checks for the local servers listening
+const SERVER_READY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Waits until all the [CRITICAL_SERVERS] are listening, provides the name
+/// of the first server that isn't listening after [SERVER_READY_TIMEOUT].
+/// Servers are checked using the listening ports of this process rather
+/// than connecting as connecting to the blaze server would open a
+/// connection to the remote server
+async fn wait_for_servers_ready() -> Result<(), &'static str> {
+    let start = Instant::now();
+
+    loop {
+        let ports = listening_ports();
+
+        let not_ready = match &ports {
+            Some(ports) => CRITICAL_SERVERS
+                .iter()
+                .find(|(port, _)| !ports.contains(port))
+                .map(|(_, name)| *name),
+            None => {
+                // Listening ports can't be checked, don't block the game
+                warn!("Unable to check local servers are listening");
+                return Ok(());
+            }
+        };
+
+        let Some(name) = not_ready else {
+            debug!(
+                "Local servers ready after {}ms",
+                start.elapsed().as_millis()
             );
+            return Ok(());
+        };
 
-            // Error while connecting UDP tunnel, fallback to HTTP upgrade tunnel
-            tunnel::start_tunnel_server(ctx).await
+        if start.elapsed() >= SERVER_READY_TIMEOUT {
+            warn!("Local {} server isn't listening", name);
+            return Err(name);
         }
-        // When unavailable fallback to the HTTP upgrade tunnel
-        None => tunnel::start_tunnel_server(ctx).await,
+
+        tokio::time::sleep(SERVER_READY_INTERVAL).await;
     }
 }
 
+/// Provides the IPv4 TCP ports this process is listening on, [None]
+/// if the listening ports couldn't be obtained
+fn listening_ports() -> Option<Vec<u16>> {
+    let rows = process_tcp_rows(TCP_TABLE_OWNER_PID_LISTENER)?;
+    Some(rows.iter().map(local_port).collect())
+}
+
+/// Checks whether the game has an established connection to the local
+/// blaze server, the local end of the server side socket is owned by
+/// this process so only the local port needs to be checked
+fn has_blaze_connection() -> bool {
+    process_tcp_rows(TCP_TABLE_OWNER_PID_ALL).is_some_and(|rows| {
+        rows.iter()
+            .any(|row| row.dwState == MIB_TCP_STATE_ESTAB as u32 && local_port(row) == BLAZE_PORT)
+    })
+}
+
+/// Provides the local port of a TCP table row
+#[inline]
+fn local_port(row: &MIB_TCPROW_OWNER_PID) -> u16 {
+    // Port is stored in network byte order in the low bits
+    u16::from_be(row.dwLocalPort as u16)
+}
+
+/// Reads the IPv4 TCP table of the provided `table_class` keeping only
+/// the rows owned by this process, [None] if the table couldn't be read
+///
+/// ## Arguments
+/// * `table_class` - The class of table to read
+fn process_tcp_rows(table_class: TCP_TABLE_CLASS) -> Option<Vec<MIB_TCPROW_OWNER_PID>> {
+    let process_id = std::process::id();
+
+    // Table size can change between calls so the size is retried
+    let mut size: u32 = 0;
+    for _ in 0..3 {
+        // u32 buffer keeps the table aligned
+        let mut buffer: Vec<u32> = vec![0; (size as usize).div_ceil(4)];
+        let result = unsafe {
+            GetExtendedTcpTable(
+                buffer.as_mut_ptr().cast(),
+                &mut size,
+                FALSE,
+                AF_INET as u32,
+                table_class,
+                0,
+            )
+        };
+
+        if result == ERROR_INSUFFICIENT_BUFFER {
+            continue;
+        }
+
+        if result != NO_ERROR {
+            return None;
+        }
+
+        let table = buffer.as_ptr().cast::<MIB_TCPTABLE_OWNER_PID>();
+        let rows = unsafe {
+            std::slice::from_raw_parts(
+                addr_of!((*table).table).cast::<MIB_TCPROW_OWNER_PID>(),
+                (*table).dwNumEntries as usize,
+            )
+        };
+
+        return Some(
+            rows.iter()
+                .filter(|row| row.dwOwningPid == process_id)
+                .copied()
+                .collect(),
+        );
+    }
+
+    None
+}
+
 /// Runs the provided server `future` in a background task displaying
 /// and logging any errors if they occur
 #[inline]
@@ -75,8 +1242,378 @@ where
 {
     spawn_server_task(async move {
         if let Err(err) = future.await {
+            clear_lookup_cache();
+            record_error(
+                ErrorCategory::Server,
+                format!("Failed to start {name} server: {err}"),
+            );
             error_message(&format!("Failed to start {name} server"), &err.to_string());
             error!("Failed to start {name} server: {err}");
         }
     });
 }
+
+/// Provides the latest measured latency to the connected server in
+/// milliseconds, [None] if the latency is unknown or the last probe failed
+pub fn latency() -> Option<u32> {
+    let latency = LATENCY.load(Ordering::Acquire);
+    if latency == LATENCY_UNKNOWN {
+        None
+    } else {
+        Some(latency)
+    }
+}
+
+/// Starts a background server task that periodically measures the latency
+/// to the server using a HEAD request to the server details endpoint. The
+/// task is stopped along with the other server tasks
+///
+/// ## Arguments
+/// * `http_client` - The HTTP client to use
+/// * `base_url`    - The base URL of the connected server
+/// * `notice`      - Sender notified after each probe
+pub fn start_latency_probe(http_client: Client, base_url: Url, notice: NoticeSender) {
+    LATENCY.store(LATENCY_UNKNOWN, Ordering::Release);
+
+    let url = match base_url.join(DETAILS_ENDPOINT) {
+        Ok(value) => value,
+        Err(err) => {
+            error!("Failed to create latency probe URL: {}", err);
+            return;
+        }
+    };
+
+    spawn_server_task(async move {
+        loop {
+            let start = Instant::now();
+            let result = http_client
+                .head(url.clone())
+                .timeout(LATENCY_PROBE_TIMEOUT)
+                .send()
+                .await;
+
+            let latency = match result {
+                Ok(_) => start.elapsed().as_millis().min(LATENCY_UNKNOWN as u128 - 1) as u32,
+                Err(err) => {
+                    debug!("Latency probe failed: {}", err);
+                    LATENCY_UNKNOWN
+                }
+            };
+
+            LATENCY.store(latency, Ordering::Release);
+
+            // Active once blaze traffic from the game reaches the local server
+            if has_blaze_connection() {
+                mark_connection_active();
+            }
+
+            notice.notice();
+
+            tokio::time::sleep(LATENCY_PROBE_INTERVAL).await;
+        }
+    });
+}
+
+/// Version details from the server details endpoint
+#[derive(Deserialize)]
+struct ServerVersion {
+    /// The Pocket Relay version of the server
+    version: Version,
+}
+
+/// Checks whether the server changing from the `connected` version to the
+/// `current` version may be incompatible with the existing connection,
+/// servers before 1.0 may break compatibility in minor versions
+///
+/// ## Arguments
+/// * `connected` - The version when connecting
+/// * `current`   - The current server version
+fn is_version_drift(connected: &Version, current: &Version) -> bool {
+    connected.major != current.major || (connected.major == 0 && connected.minor != current.minor)
+}
+
+/// Starts a background server task that periodically requests the server
+/// version, when the server is updated to a version that may be incompatible
+/// or is below the `min_version` the player is told to reconnect using the
+/// in-game terminal. The task stops after notifying and is stopped along with
+/// the other server tasks
+///
+/// ## Arguments
+/// * `http_client` - The HTTP client to use
+/// * `base_url`    - The base URL of the connected server
+/// * `version`     - The server version when connecting
+/// * `min_version` - The minimum allowed server version
+/// * `interval`    - Time between checks
+pub fn start_version_check(
+    http_client: Client,
+    base_url: Url,
+    version: Version,
+    min_version: Version,
+    interval: Duration,
+) {
+    let url = match base_url.join(DETAILS_ENDPOINT) {
+        Ok(value) => value,
+        Err(err) => {
+            error!("Failed to create version check URL: {}", err);
+            return;
+        }
+    };
+
+    spawn_server_task(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let result = match http_client.get(url.clone()).send().await {
+                Ok(response) => response.json::<ServerVersion>().await,
+                Err(err) => Err(err),
+            };
+
+            let current = match result {
+                Ok(value) => value.version,
+                Err(err) => {
+                    debug!("Server version check failed: {}", err);
+                    continue;
+                }
+            };
+
+            let message = if current < min_version {
+                format!(
+                    "The server was changed to version v{} which is below the minimum \
+                    supported version v{}, you may experience issues until it's updated",
+                    current, min_version
+                )
+            } else if is_version_drift(&version, &current) {
+                format!(
+                    "The server was updated from v{} to v{}, return to the main menu \
+                    and reconnect in the Pocket Relay window to avoid issues",
+                    version, current
+                )
+            } else {
+                continue;
+            };
+
+            warn!("Server version changed while connected: {}", message);
+            set_version_warning(Some(message));
+            return;
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        cache_lookup, cached_lookup, check_redirect, clear_lookup_cache, is_non_json_error,
+        local_server_port, normalize_target, sanitize_target, stop_all_servers,
+        url_local_server_port, LookupData, ServerVersion, Url, Version, BLAZE_PORT, HTTP_PORT,
+        MAX_REDIRECTS, REDIRECTOR_PORT,
+    };
+
+    /// Markdown links use the link URL
+    #[test]
+    fn test_sanitize_markdown_link() {
+        assert_eq!(
+            sanitize_target("[My Server](https://example.com/)"),
+            "https://example.com/"
+        );
+        assert_eq!(
+            sanitize_target("  [example.com](http://example.com:8080)  "),
+            "http://example.com:8080"
+        );
+    }
+
+    /// Angle brackets, quotes and backticks around the URL are removed
+    #[test]
+    fn test_sanitize_wrapped() {
+        assert_eq!(
+            sanitize_target("<https://example.com>"),
+            "https://example.com"
+        );
+        assert_eq!(
+            sanitize_target("\"https://example.com\""),
+            "https://example.com"
+        );
+        assert_eq!(sanitize_target("'example.com'"), "example.com");
+        assert_eq!(sanitize_target("`example.com`"), "example.com");
+        assert_eq!(sanitize_target("(example.com)"), "example.com");
+    }
+
+    /// Punctuation from the end of a sentence is removed
+    #[test]
+    fn test_sanitize_trailing_punctuation() {
+        assert_eq!(
+            sanitize_target("https://example.com."),
+            "https://example.com"
+        );
+        assert_eq!(sanitize_target("example.com/!?"), "example.com/");
+        assert_eq!(sanitize_target("<example.com>,"), "example.com");
+    }
+
+    /// Repeated trailing slashes are collapsed, a bare scheme is kept
+    #[test]
+    fn test_sanitize_trailing_slashes() {
+        assert_eq!(
+            sanitize_target("https://example.com///"),
+            "https://example.com/"
+        );
+        assert_eq!(sanitize_target("https://"), "https://");
+    }
+
+    /// Protocol relative URLs have the scheme inferred by the lookup
+    #[test]
+    fn test_sanitize_protocol_relative() {
+        assert_eq!(sanitize_target("//example.com"), "example.com");
+        assert_eq!(
+            sanitize_target("<//example.com:8080/>"),
+            "example.com:8080/"
+        );
+    }
+
+    /// Deep links are converted to HTTP URLs
+    #[test]
+    fn test_normalize_deep_link() {
+        assert_eq!(
+            normalize_target("pocketrelay://example.com"),
+            "http://example.com"
+        );
+        assert_eq!(
+            normalize_target("PocketRelay://example.com:8080/"),
+            "http://example.com:8080"
+        );
+        assert_eq!(
+            normalize_target("pocketrelay://https://example.com/"),
+            "https://example.com"
+        );
+        assert_eq!(
+            normalize_target("[Join](pocketrelay://example.com)."),
+            "http://example.com"
+        );
+    }
+
+    /// Targets that aren't deep links are only sanitized
+    #[test]
+    fn test_normalize_other_targets() {
+        assert_eq!(normalize_target(" example.com "), "example.com");
+        assert_eq!(
+            normalize_target("\"https://example.com/\""),
+            "https://example.com/"
+        );
+        assert_eq!(normalize_target("//example.com"), "example.com");
+    }
+
+    /// Targets pointing at the local servers are rejected before lookup
+    #[test]
+    fn test_local_server_target() {
+        assert_eq!(
+            local_server_port(&format!("127.0.0.1:{HTTP_PORT}")),
+            Some(HTTP_PORT)
+        );
+        assert_eq!(
+            local_server_port(&format!("http://localhost:{REDIRECTOR_PORT}/")),
+            Some(REDIRECTOR_PORT)
+        );
+        assert_eq!(
+            local_server_port(&format!("https://[::1]:{BLAZE_PORT}")),
+            Some(BLAZE_PORT)
+        );
+        assert_eq!(local_server_port("127.0.0.1"), None);
+        assert_eq!(local_server_port("127.0.0.1:8080"), None);
+        assert_eq!(local_server_port(&format!("example.com:{HTTP_PORT}")), None);
+    }
+
+    /// Servers that resolve to the local servers after lookup are rejected
+    #[test]
+    fn test_local_server_resolved_url() {
+        let url = Url::parse(&format!("http://127.0.0.1:{HTTP_PORT}/")).unwrap();
+        assert_eq!(url_local_server_port(&url), Some(HTTP_PORT));
+
+        let url = Url::parse("https://example.com/").unwrap();
+        assert_eq!(url_local_server_port(&url), None);
+    }
+
+    /// Lookups are kept for reconnecting after a disconnect and are
+    /// removed when a connection fails
+    #[test]
+    fn test_lookup_cache_reconnect() {
+        let target = "http://cache-test.example.com".to_string();
+        let lookup = LookupData {
+            url: Url::parse("http://cache-test.example.com/").unwrap(),
+            version: Version::new(0, 6, 0),
+            association: None,
+            tunnel_port: None,
+        };
+
+        // Connect
+        cache_lookup(target.clone(), lookup, false);
+        assert!(cached_lookup(&target).is_some());
+
+        // Disconnect
+        stop_all_servers();
+
+        // Reconnect uses the cached lookup
+        let cached = cached_lookup(&target).expect("Lookup should be cached after disconnect");
+        assert_eq!(cached.url.as_str(), "http://cache-test.example.com/");
+
+        // Failed connection clears the cache
+        clear_lookup_cache();
+        assert!(cached_lookup(&target).is_none());
+    }
+
+    /// Redirects on the same host are followed, including scheme upgrades
+    #[test]
+    fn test_redirect_same_host() {
+        let previous = [Url::parse("http://example.com/api/server").unwrap()];
+
+        let next = Url::parse("https://example.com/api/server").unwrap();
+        assert!(check_redirect(&previous, &next).is_ok());
+
+        let next = Url::parse("http://example.com:8080/api/server").unwrap();
+        assert!(check_redirect(&previous, &next).is_ok());
+    }
+
+    /// Redirects to another host are rejected
+    #[test]
+    fn test_redirect_cross_host() {
+        let previous = [Url::parse("http://example.com/api/server").unwrap()];
+
+        let next = Url::parse("https://other.example.com/api/server").unwrap();
+        let err = check_redirect(&previous, &next).unwrap_err();
+        assert!(err.contains("other.example.com"));
+
+        // Host of the original request is used, not the last redirect
+        let previous = [
+            Url::parse("http://example.com/").unwrap(),
+            Url::parse("https://example.com/").unwrap(),
+        ];
+        assert!(check_redirect(&previous, &next).is_err());
+    }
+
+    /// At most [MAX_REDIRECTS] redirects are followed
+    #[test]
+    fn test_redirect_limit() {
+        let url = Url::parse("https://example.com/").unwrap();
+
+        // Original request followed by the redirects already taken
+        let previous = vec![url.clone(); MAX_REDIRECTS];
+        assert!(check_redirect(&previous, &url).is_ok());
+
+        let previous = vec![url.clone(); MAX_REDIRECTS + 1];
+        assert!(check_redirect(&previous, &url).is_err());
+    }
+
+    /// Only bodies that aren't JSON at all are unexpected content
+    #[test]
+    fn test_is_non_json_error() {
+        let is_non_json = |body: &str| {
+            serde_json::from_str::<ServerVersion>(body)
+                .err()
+                .is_some_and(|err| is_non_json_error(&err))
+        };
+
+        assert!(is_non_json("<!DOCTYPE html><html></html>"));
+        assert!(is_non_json("  \n<html></html>"));
+        assert!(is_non_json("Please sign in to continue"));
+        assert!(!is_non_json(r#"{"version":"invalid"}"#));
+        assert!(!is_non_json("{}"));
+        assert!(!is_non_json(""));
+    }
+}
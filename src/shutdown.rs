@@ -0,0 +1,97 @@
+//! Shutdown handling for when the plugin is unloaded while the game keeps
+//! running (Mod managers unloading plugins), the UI and tokio runtime must
+//! be stopped before the plugin memory is freed
+
+use log::{debug, warn};
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Condvar, Mutex,
+    },
+    time::Duration,
+};
+use tokio::sync::Notify;
+use windows_sys::Win32::{
+    System::Threading::GetCurrentThreadId,
+    UI::WindowsAndMessaging::{PostThreadMessageW, WM_QUIT},
+};
+
+/// Maximum time to wait for the UI thread to stop when unloading, also
+/// used by the UI thread as the limit for stopping the runtime
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Notified when the runtime should stop
+static SHUTDOWN: Notify = Notify::const_new();
+
+/// ID of the thread running the UI event loop, zero when not running
+static UI_THREAD_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Whether the UI thread has finished stopping the UI and runtime
+static STOPPED: Mutex<bool> = Mutex::new(false);
+/// Condition notified when [STOPPED] is set
+static STOPPED_CONDVAR: Condvar = Condvar::new();
+
+/// Stores the current thread as the UI thread, the UI event loop is
+/// stopped on this thread when shutting down
+pub fn set_ui_thread() {
+    UI_THREAD_ID.store(unsafe { GetCurrentThreadId() }, Ordering::Release);
+}
+
+/// Waits until a shutdown is requested by [shutdown]
+pub async fn wait_for_shutdown() {
+    SHUTDOWN.notified().await;
+}
+
+/// Guard held by the UI thread, marks the UI and runtime as stopped when
+/// dropped (including when the UI thread panics) releasing a pending
+/// [shutdown]. Must be the last plugin value dropped by the UI thread
+pub struct StoppedGuard;
+
+impl Drop for StoppedGuard {
+    fn drop(&mut self) {
+        UI_THREAD_ID.store(0, Ordering::Release);
+
+        if let Ok(mut stopped) = STOPPED.lock() {
+            *stopped = true;
+            STOPPED_CONDVAR.notify_all();
+        }
+    }
+}
+
+/// Signals the UI event loop and runtime to stop and waits up to
+/// [SHUTDOWN_TIMEOUT] for the UI thread to finish stopping them, the
+/// teardown itself runs on the UI thread. Returns whether it stopped
+///
+/// Called from DllMain so the loader lock is held. The UI thread handle
+/// isn't waited on as a thread can't finish exiting until the lock is
+/// released, instead the wait ends once the UI thread has dropped its
+/// [StoppedGuard]. Teardown that needs the loader lock (loading a DLL,
+/// joining threads) would block until the timeout so the UI thread must
+/// avoid it. The wait is bounded so the game isn't hung if it doesn't stop
+pub fn shutdown() -> bool {
+    debug!("Shutting down");
+
+    // Stored as a permit when the runtime isn't waiting yet
+    SHUTDOWN.notify_one();
+
+    // Stop the UI event loop so the UI thread can reach the runtime shutdown
+    let ui_thread_id = UI_THREAD_ID.load(Ordering::Acquire);
+    if ui_thread_id != 0 {
+        unsafe { PostThreadMessageW(ui_thread_id, WM_QUIT, 0, 0) };
+    }
+
+    let Ok(stopped) = STOPPED.lock() else {
+        return false;
+    };
+
+    let stopped = STOPPED_CONDVAR
+        .wait_timeout_while(stopped, SHUTDOWN_TIMEOUT, |stopped| !*stopped)
+        .map(|(stopped, _)| *stopped)
+        .unwrap_or_default();
+
+    if !stopped {
+        warn!("Timed out waiting for the UI thread to stop");
+    }
+
+    stopped
+}
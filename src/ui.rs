@@ -1,33 +1,74 @@
 use crate::{
-    config::{write_config_file, ClientConfig},
-    core::{
-        api::{lookup_server, LookupData, LookupError},
-        reqwest::Client,
-        servers::{has_server_tasks, stop_server_tasks},
+    args::launch_args,
+    config::{
+        active_config_path, flush_config_write, queue_config_write, read_config_file,
+        unix_timestamp, ClientConfig, RecentServer,
     },
-    servers::start_all_servers,
+    connection::{connection_state, set_connection_state, ConnectionState},
+    core::{api::LookupData, reqwest::Client, servers::has_server_tasks, MIN_SERVER_VERSION},
+    diagnostics::{
+        last_error, record_error, run_connection_checks, ConnectionCheck, ErrorCategory,
+        LAST_ERROR_FILE_NAME,
+    },
+    hooks::{
+        hook_status,
+        host_lookup::{is_force_offline, redirect_stats, set_force_offline},
+        process_event::{message_history, set_trace_events},
+    },
+    hotkey::{start_hotkey_listener, Hotkey},
+    launcher,
+    servers::{self, ConnectError, ConnectOptions, ConnectedServer},
+    shutdown,
     threads::resume_all_threads,
     update,
 };
 use futures::FutureExt;
+use log::{debug, error, warn};
 use native_windows_derive::NwgUi;
 use native_windows_gui::{init as nwg_init, *};
-use pocket_relay_client_shared::ctx::ClientContext;
-use std::{cell::RefCell, sync::Arc};
+use std::{
+    cell::{Cell, RefCell},
+    ffi::OsStr,
+    iter::once,
+    os::windows::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    ptr::null,
+    time::{Duration, Instant},
+};
 use tokio::task::JoinHandle;
+use windows_sys::Win32::UI::{Shell::ShellExecuteW, WindowsAndMessaging::SW_SHOWNORMAL};
 
 /// Size of the created window
-pub const WINDOW_SIZE: (i32, i32) = (500, 225);
+pub const WINDOW_SIZE: (i32, i32) = (500, 320);
 /// Title used for the created window
 pub const WINDOW_TITLE: &str = concat!("Pocket Relay Client v", env!("CARGO_PKG_VERSION"));
 /// Window icon bytes
 pub const ICON_BYTES: &[u8] = include_bytes!("resources/icon.ico");
+/// Name of the icon file within a resource pack
+const RESOURCE_PACK_ICON: &str = "icon.ico";
+/// Text explaining how connecting works
+const CONNECT_LABEL_TEXT: &str = "Your game will start after you connect. If you don't want to connect to\n a Pocket Relay server press 'Play Offline' and you will connect to the\n official servers";
+/// Explanation shown the first time the plugin is used
+const TUTORIAL_TEXT: &str = "Welcome to Pocket Relay!\n\n\
+    To play on a Pocket Relay server you need its Connection URL, ask the person \
+    hosting the server for it. Paste the Connection URL into the box and press \
+    'Connect', your game will start once you are connected.\n\n\
+    Servers you have connected to are saved in the dropdown below the Connection URL \
+    and 'Reconnect Last' connects to the last server you used.\n\n\
+    To play on the official servers instead press 'Play Offline'.";
+/// Time the connect button is disabled for after connecting or disconnecting
+const CONNECT_COOLDOWN: Duration = Duration::from_millis(750);
+/// Text shown while an update is being downloaded
+const UPDATING_LABEL_TEXT: &str =
+    "Updating Pocket Relay plugin...\n\nPlease wait, the game will close once the update is complete";
 
 /// Native GUI app
+///
+/// Controls are created in the order of the fields below which also sets
+/// the tab order, keep the grid controls declared row by row, left to right
 #[derive(NwgUi, Default)]
 pub struct App {
-    /// Window Icon
-    #[nwg_resource(source_bin: Some(ICON_BYTES))]
+    /// Window Icon, loaded before building the UI by [load_icon]
     icon: Icon,
 
     /// App window
@@ -38,9 +79,43 @@ pub struct App {
         title: WINDOW_TITLE,
         flags: "WINDOW|VISIBLE|MINIMIZE_BOX"
     )]
-    #[nwg_events(OnWindowClose: [stop_thread_dispatch()], OnKeyEnter: [App::handle_set])]
+    #[nwg_events(OnWindowClose: [stop_thread_dispatch()], OnKeyEnter: [App::handle_enter_key])]
     window: Window,
 
+    /// System tray icon
+    #[nwg_control(icon: Some(&data.icon), tip: Some(WINDOW_TITLE))]
+    #[nwg_events(MousePressLeftUp: [App::handle_show_window], OnContextMenu: [App::handle_show_tray_menu])]
+    tray: TrayNotification,
+
+    /// System tray popup menu
+    #[nwg_control(parent: window, popup: true)]
+    tray_menu: Menu,
+
+    /// Tray menu item for connecting
+    #[nwg_control(parent: tray_menu, text: "Connect")]
+    #[nwg_events(OnMenuItemSelected: [App::handle_tray_connect])]
+    tray_connect_item: MenuItem,
+
+    /// Tray menu item for disconnecting
+    #[nwg_control(parent: tray_menu, text: "Disconnect")]
+    #[nwg_events(OnMenuItemSelected: [App::handle_tray_disconnect])]
+    tray_disconnect_item: MenuItem,
+
+    /// Tray menu item for reconnecting to the last server
+    #[nwg_control(parent: tray_menu, text: "Reconnect to Last Server")]
+    #[nwg_events(OnMenuItemSelected: [App::handle_reconnect_last])]
+    tray_reconnect_item: MenuItem,
+
+    /// Tray menu item for checking for updates
+    #[nwg_control(parent: tray_menu, text: "Check for Updates")]
+    #[nwg_events(OnMenuItemSelected: [App::handle_check_updates])]
+    tray_update_item: MenuItem,
+
+    /// Tray menu item for closing the window
+    #[nwg_control(parent: tray_menu, text: "Quit")]
+    #[nwg_events(OnMenuItemSelected: [App::handle_tray_quit])]
+    tray_quit_item: MenuItem,
+
     /// Grid layout for all the content
     #[nwg_layout(parent: window)]
     grid: GridLayout,
@@ -50,9 +125,16 @@ pub struct App {
     #[nwg_layout_item(layout: grid, col: 0, row: 0, col_span: 2)]
     target_url_label: Label,
 
+    /// Button for reconnecting to the last server
+    #[nwg_control(text: "Reconnect Last")]
+    #[nwg_layout_item(layout: grid, col: 2, row: 0, col_span: 1)]
+    #[nwg_events(OnButtonClick: [App::handle_reconnect_last])]
+    reconnect_button: Button,
+
     /// Input for the connection URL
     #[nwg_control(focus: true)]
     #[nwg_layout_item(layout: grid, col: 0, row: 1, col_span: 2)]
+    #[nwg_events(OnKeyEnter: [App::handle_enter_key])]
     target_url_input: TextInput,
 
     /// Button for connecting
@@ -61,58 +143,237 @@ pub struct App {
     #[nwg_events(OnButtonClick: [App::handle_set])]
     set_button: Button,
 
+    /// Dropdown of recently connected servers
+    #[nwg_control]
+    #[nwg_layout_item(layout: grid, col: 0, row: 2, col_span: 2)]
+    #[nwg_events(OnComboxBoxSelection: [App::handle_recent_selected])]
+    recent_select: ComboBox<String>,
+
+    /// Button for reloading the config file
+    #[nwg_control(text: "Reload Config")]
+    #[nwg_layout_item(layout: grid, col: 2, row: 2, col_span: 1)]
+    #[nwg_events(OnButtonClick: [App::handle_reload_config])]
+    reload_button: Button,
+
     /// Checkbox for whether to remember the connection URL
     #[nwg_control(text: "Save connection URL")]
-    #[nwg_layout_item(layout: grid, col: 0, row: 2, col_span: 3)]
+    #[nwg_layout_item(layout: grid, col: 0, row: 3, col_span: 2)]
+    #[nwg_events(OnButtonClick: [App::handle_remember_changed])]
     remember_checkbox: CheckBox,
 
+    /// Button for playing without a server
+    #[nwg_control(text: "Play Offline")]
+    #[nwg_layout_item(layout: grid, col: 2, row: 3, col_span: 1)]
+    #[nwg_events(OnButtonClick: [App::handle_play_offline])]
+    offline_button: Button,
+
     /// Connection state label
     #[nwg_control(text: "Not connected")]
-    #[nwg_layout_item(layout: grid, col: 0, row: 3, col_span: 3)]
+    #[nwg_layout_item(layout: grid, col: 0, row: 4, col_span: 2)]
     connection_label: Label,
 
+    /// Button for viewing recent server messages
+    #[nwg_control(text: "Messages")]
+    #[nwg_layout_item(layout: grid, col: 2, row: 4, col_span: 1)]
+    #[nwg_events(OnButtonClick: [App::handle_show_messages])]
+    messages_button: Button,
+
     /// Label about connecting
-    #[nwg_control(text: "Your game will start after you connect. If you don't want to connect to\n a Pocket Relay server close this window and you will connect to the\n official servers")]
-    #[nwg_layout_item(layout: grid, col: 0, row: 4, col_span: 3, row_span: 3)]
+    #[nwg_control(text: CONNECT_LABEL_TEXT)]
+    #[nwg_layout_item(layout: grid, col: 0, row: 5, col_span: 2, row_span: 3)]
     connect_label: Label,
 
+    /// Button for viewing the last recorded error
+    #[nwg_control(text: "Last Error")]
+    #[nwg_layout_item(layout: grid, col: 2, row: 5, col_span: 1)]
+    #[nwg_events(OnButtonClick: [App::handle_show_last_error])]
+    last_error_button: Button,
+
+    /// Button for exporting the settings to a file
+    #[nwg_control(text: "Export Settings")]
+    #[nwg_layout_item(layout: grid, col: 2, row: 6, col_span: 1)]
+    #[nwg_events(OnButtonClick: [App::handle_export_settings])]
+    export_button: Button,
+
+    /// Button for importing settings from a file
+    #[nwg_control(text: "Import Settings")]
+    #[nwg_layout_item(layout: grid, col: 2, row: 7, col_span: 1)]
+    #[nwg_events(OnButtonClick: [App::handle_import_settings])]
+    import_button: Button,
+
+    /// Input for the minimum server version override
+    #[nwg_control(placeholder_text: Some("Minimum server version"))]
+    #[nwg_layout_item(layout: grid, col: 0, row: 8, col_span: 2)]
+    min_version_input: TextInput,
+
+    /// Button for saving the minimum server version override
+    #[nwg_control(text: "Set Min Version")]
+    #[nwg_layout_item(layout: grid, col: 2, row: 8, col_span: 1)]
+    #[nwg_events(OnButtonClick: [App::handle_set_min_version])]
+    min_version_button: Button,
+
+    /// "Run Diagnostics" button for checking each stage of the connection
+    #[nwg_control(text: "Run Diagnostics")]
+    #[nwg_layout_item(layout: grid, col: 1, row: 9, col_span: 1)]
+    #[nwg_events(OnButtonClick: [App::handle_run_diagnostics])]
+    diagnostics_button: Button,
+
+    /// Button for opening the folder containing the config file
+    #[nwg_control(text: "Open Config Folder")]
+    #[nwg_layout_item(layout: grid, col: 2, row: 9, col_span: 1)]
+    #[nwg_events(OnButtonClick: [App::handle_open_config_folder])]
+    config_folder_button: Button,
+
     /// Notice for connection completion
     #[nwg_control]
     #[nwg_events(OnNotice: [App::handle_connect_notice])]
     connect_notice: Notice,
 
+    /// Notice for update progress changes
+    #[nwg_control]
+    #[nwg_events(OnNotice: [App::handle_update_notice])]
+    update_notice: Notice,
+
+    /// Notice for latency probe results
+    #[nwg_control]
+    #[nwg_events(OnNotice: [App::handle_latency_notice])]
+    latency_notice: Notice,
+
+    /// Notice for the connection checks completing
+    #[nwg_control]
+    #[nwg_events(OnNotice: [App::handle_diagnostics_notice])]
+    diagnostics_notice: Notice,
+
+    /// Notice for the offline mode hotkey being pressed
+    #[nwg_control]
+    #[nwg_events(OnNotice: [App::handle_offline_hotkey])]
+    offline_notice: Notice,
+
+    /// Notice for the connect button cooldown ending
+    #[nwg_control]
+    #[nwg_events(OnNotice: [App::handle_cooldown_notice])]
+    cooldown_notice: Notice,
+
+    /// Notice for launcher requests changing the connection
+    #[nwg_control]
+    #[nwg_events(OnNotice: [App::handle_launcher_notice])]
+    launcher_notice: Notice,
+
+    /// When the current connect button cooldown ends
+    cooldown: RefCell<Cooldown>,
+
     /// Join handle for the connect task
-    connect_task: RefCell<Option<JoinHandle<Result<LookupData, LookupError>>>>,
+    connect_task: RefCell<Option<JoinHandle<Result<ConnectedServer, ConnectError>>>>,
+
+    /// Task running the connection checks
+    diagnostics_task: RefCell<Option<JoinHandle<Vec<ConnectionCheck>>>>,
 
     /// Http client for sending requests
     http_client: Client,
+
+    /// The loaded client config
+    config: RefCell<ClientConfig>,
+
+    /// Connected server details shown after the connection state
+    connected_text: RefCell<String>,
+
+    /// Whether a connection was running when offline mode was entered
+    /// and should be reconnected when leaving offline mode
+    reconnect_after_offline: Cell<bool>,
+}
+
+/// Tracks the connect button cooldown, overlapping cooldowns (e.g. a
+/// disconnect straight after connecting) end with the latest cooldown
+#[derive(Debug, Default)]
+struct Cooldown {
+    /// When the cooldown ends, [None] if no cooldown was started
+    until: Option<Instant>,
+}
+
+impl Cooldown {
+    /// Starts a cooldown of [CONNECT_COOLDOWN] at `now`, an active cooldown
+    /// that would end later is kept. Provides the time until it ends
+    ///
+    /// ## Arguments
+    /// * `now` - The current time
+    fn start(&mut self, now: Instant) -> Duration {
+        let until = now + CONNECT_COOLDOWN;
+        let until = self.until.map_or(until, |current| current.max(until));
+        self.until = Some(until);
+        until - now
+    }
+
+    /// Provides the time remaining until the cooldown ends, [None] when
+    /// the cooldown has ended
+    ///
+    /// ## Arguments
+    /// * `now` - The current time
+    fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.until
+            .and_then(|until| until.checked_duration_since(now))
+            .filter(|remaining| !remaining.is_zero())
+    }
 }
 
 impl App {
     /// Handles the "Set" button being pressed, dispatches a connect task
     /// that will wake up the App with `App::handle_connect_notice` to
-    /// handle the connection result.
+    /// handle the connection result. While connecting the button cancels
+    /// the connection attempt and while connected it disconnects
     fn handle_set(&self) {
-        // Abort any existing connection tasks
+        // Button is disabled during the cooldown or while updating, the
+        // tray menu items and enter key must respect this too
+        if !self.set_button.enabled() {
+            return;
+        }
+
+        // Cancel the connection attempt if one is in progress
         if let Some(task) = self.connect_task.take() {
             task.abort();
+
+            // Servers may have been started while waiting for them to listen
+            if has_server_tasks() {
+                servers::stop_all_servers();
+            }
+
+            set_connection_state(ConnectionState::Disconnected);
+            self.connection_label.set_text("Not connected");
+            self.set_button.set_text("Connect");
+            self.start_cooldown();
+            return;
         }
 
         // Handle disconnecting
         if has_server_tasks() {
-            stop_server_tasks();
+            servers::stop_all_servers();
+            set_connection_state(ConnectionState::Disconnected);
+            flush_config_write();
             self.connection_label.set_text("Not connected");
             self.set_button.set_text("Connect");
+            self.start_cooldown();
+            return;
+        }
+
+        if !set_connection_state(ConnectionState::Connecting) {
             return;
         }
 
         self.connection_label.set_text("Connecting...");
-        let target = self.target_url_input.text().to_string();
+        self.set_button.set_text("Cancel");
+
+        // Normalize deep links and fill in the normalized URL
+        let input = self.target_url_input.text();
+        let target = servers::normalize_target(&input);
+        if target != input {
+            self.target_url_input.set_text(&target);
+        }
+
         let sender = self.connect_notice.sender();
         let http_client = self.http_client.clone();
+        let options = ConnectOptions::from_config(&self.config.borrow());
 
         let task = tokio::spawn(async move {
-            let result = lookup_server(http_client, target).await;
+            let result = servers::connect(http_client, target, options).await;
             sender.notice();
             result
         });
@@ -120,6 +381,704 @@ impl App {
         *self.connect_task.borrow_mut() = Some(task);
     }
 
+    /// Disables the connect button for [CONNECT_COOLDOWN] after a connection
+    /// attempt ends, giving the server tasks time to finish stopping before
+    /// they can be started again
+    fn start_cooldown(&self) {
+        self.set_button.set_enabled(false);
+
+        let delay = self.cooldown.borrow_mut().start(Instant::now());
+        self.schedule_cooldown_notice(delay);
+    }
+
+    /// Notifies the [App::cooldown_notice] after `delay`
+    ///
+    /// ## Arguments
+    /// * `delay` - Time to wait before notifying
+    fn schedule_cooldown_notice(&self, delay: Duration) {
+        let sender = self.cooldown_notice.sender();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            sender.notice();
+        });
+    }
+
+    /// Handles a connect button cooldown ending, the button stays disabled
+    /// when a later cooldown was started in the meantime
+    fn handle_cooldown_notice(&self) {
+        let remaining = self.cooldown.borrow().remaining(Instant::now());
+        if let Some(remaining) = remaining {
+            self.schedule_cooldown_notice(remaining);
+            return;
+        }
+
+        // Updating keeps the button disabled
+        self.set_button.set_enabled(!update::is_updating());
+    }
+
+    /// Handles the enter key being pressed within the window (including
+    /// within the connection URL input), connects using the same logic as
+    /// the "Connect" button. Unlike the button, enter never disconnects or
+    /// restarts a connection that is already in progress
+    fn handle_enter_key(&self) {
+        if connection_state() != ConnectionState::Disconnected || !self.set_button.enabled() {
+            return;
+        }
+
+        self.handle_set();
+    }
+
+    /// Shows the window when the tray icon is clicked
+    fn handle_show_window(&self) {
+        self.window.set_visible(true);
+        self.window.set_focus();
+    }
+
+    /// Shows the tray popup menu at the cursor position
+    fn handle_show_tray_menu(&self) {
+        let (x, y) = GlobalCursor::position();
+        self.tray_menu.popup(x, y);
+    }
+
+    /// Handles the tray "Connect" item, connecting to the current
+    /// connection URL if not already connected
+    fn handle_tray_connect(&self) {
+        if connection_state() == ConnectionState::Disconnected {
+            self.handle_set();
+        }
+    }
+
+    /// Handles the tray "Disconnect" item, stopping the servers if
+    /// they are running or cancelling the connection attempt
+    fn handle_tray_disconnect(&self) {
+        if connection_state() != ConnectionState::Disconnected {
+            self.handle_set();
+        }
+    }
+
+    /// Handles the "Reconnect Last" button and tray item, connects to the
+    /// most recently connected server (or the saved connection URL) without
+    /// needing the connection URL to be entered
+    fn handle_reconnect_last(&self) {
+        if connection_state() != ConnectionState::Disconnected {
+            return;
+        }
+
+        let target = {
+            let config = self.config.borrow();
+            config
+                .recent_servers
+                .first()
+                .map(|server| server.url.clone())
+                .unwrap_or_else(|| config.connection_url.clone())
+        };
+
+        if target.is_empty() {
+            info_message(
+                "No previous server",
+                "You haven't connected to a server yet, enter a connection URL to connect",
+            );
+            return;
+        }
+
+        self.target_url_input.set_text(&target);
+        self.handle_set();
+    }
+
+    /// Handles the tray "Check for Updates" item, checks for updates
+    /// even when automatic updates are disabled
+    fn handle_check_updates(&self) {
+        if update::is_updating() {
+            return;
+        }
+
+        tokio::spawn(update::update(
+            self.http_client.clone(),
+            self.update_notice.sender(),
+            true,
+        ));
+    }
+
+    /// Handles the tray "Quit" item, closing the window the same
+    /// way as closing it normally
+    fn handle_tray_quit(&self) {
+        stop_thread_dispatch();
+    }
+
+    /// Handles a latency probe completing, updates the connection
+    /// label with the latest latency
+    fn handle_latency_notice(&self) {
+        // Ignore probes that completed after disconnecting
+        let state = connection_state();
+        if !state.is_connected() {
+            return;
+        }
+
+        let latency = match servers::latency() {
+            Some(latency) => format!("{latency}ms"),
+            None => "—".to_string(),
+        };
+
+        // Shows whether the game has actually been redirected to the servers
+        let redirects = match redirect_stats() {
+            (count, Some(last)) => format!(
+                "{} redirects, last {}",
+                count,
+                format_elapsed(unix_timestamp().saturating_sub(last))
+            ),
+            (_, None) => "0 redirects".to_string(),
+        };
+
+        let text = format!(
+            "{}: {} (ping {}, {})",
+            state,
+            self.connected_text.borrow(),
+            latency,
+            redirects
+        );
+        self.connection_label.set_text(&text);
+    }
+
+    /// Shows the connection to the `lookup` server in the connection label
+    ///
+    /// ## Arguments
+    /// * `lookup` - The connected server
+    fn set_connected_text(&self, lookup: &LookupData) {
+        let text = format!(
+            "{} {} version v{}",
+            lookup.url.scheme(),
+            lookup.url.authority(),
+            lookup.version
+        );
+        self.connection_label
+            .set_text(&format!("{}: {}", ConnectionState::Connected, text));
+        *self.connected_text.borrow_mut() = text;
+    }
+
+    /// Handles a launcher request changing the connection, updates the
+    /// connection label and button to match the new connection state
+    fn handle_launcher_notice(&self) {
+        // Connection attempts from the UI update the UI themselves
+        if self.connect_task.borrow().is_some() {
+            return;
+        }
+
+        match connection_state() {
+            ConnectionState::Connecting => {
+                self.connection_label.set_text("Connecting...");
+            }
+            ConnectionState::Disconnected => {
+                self.connection_label.set_text("Not connected");
+                self.set_button.set_text("Connect");
+            }
+            ConnectionState::Connected | ConnectionState::Active => {
+                let Some(lookup) = launcher::take_launcher_connection() else {
+                    return;
+                };
+
+                self.set_connected_text(&lookup);
+                self.set_button.set_text("Disconnect");
+
+                servers::start_latency_probe(
+                    self.http_client.clone(),
+                    lookup.url.clone(),
+                    self.latency_notice.sender(),
+                );
+            }
+        }
+    }
+
+    /// Handles the offline mode hotkey, entering offline mode stops the
+    /// servers so the game uses the official servers, leaving offline mode
+    /// reconnects to the last server if a connection was running when
+    /// offline mode was entered
+    fn handle_offline_hotkey(&self) {
+        if is_force_offline() {
+            set_force_offline(false);
+            debug!("Leaving offline mode");
+
+            self.connection_label.set_text("Not connected");
+            if self.reconnect_after_offline.take() {
+                self.handle_reconnect_last();
+            }
+            return;
+        }
+
+        debug!("Entering offline mode");
+        set_force_offline(true);
+
+        // Connections still being established count as running
+        self.reconnect_after_offline
+            .set(connection_state() != ConnectionState::Disconnected);
+
+        if let Some(task) = self.connect_task.take() {
+            task.abort();
+        }
+
+        if has_server_tasks() {
+            servers::stop_all_servers();
+            flush_config_write();
+        }
+
+        set_connection_state(ConnectionState::Disconnected);
+        self.set_button.set_text("Connect");
+        self.connection_label
+            .set_text(if self.reconnect_after_offline.get() {
+                "Offline mode (press the offline hotkey to reconnect)"
+            } else {
+                "Offline mode (press the offline hotkey to leave)"
+            });
+
+        // Offline mode may be entered before the game has started
+        resume_all_threads();
+    }
+
+    /// Handles the "Play Offline" button being pressed, stops any running
+    /// servers, resumes the game threads and closes the window
+    fn handle_play_offline(&self) {
+        // Abort any existing connection tasks
+        if let Some(task) = self.connect_task.take() {
+            task.abort();
+        }
+
+        // Playing offline shouldn't keep any servers running
+        if has_server_tasks() {
+            servers::stop_all_servers();
+        }
+
+        set_connection_state(ConnectionState::Disconnected);
+
+        // Resume game threads
+        resume_all_threads();
+
+        self.window.close();
+    }
+
+    /// Handles the "Messages" button being pressed, shows the recent
+    /// messages the server displayed in the game
+    fn handle_show_messages(&self) {
+        let messages = message_history();
+        if messages.is_empty() {
+            info_message("Server messages", "No messages have been received");
+            return;
+        }
+
+        let now = unix_timestamp();
+        let text = messages
+            .iter()
+            .map(|message| {
+                format!(
+                    "[{}] {}\n{}",
+                    format_elapsed(now.saturating_sub(message.received_at)),
+                    message.title,
+                    message.message
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        info_message("Server messages", &text);
+    }
+
+    /// Shows the last recorded error
+    fn handle_show_last_error(&self) {
+        let Some(last_error) = last_error() else {
+            info_message("Last error", "No errors have occurred");
+            return;
+        };
+
+        let text = format!(
+            "[{}] {} error\n\n{}\n\nThis error was also saved to {}",
+            format_elapsed(unix_timestamp().saturating_sub(last_error.timestamp)),
+            last_error.category,
+            last_error.message,
+            LAST_ERROR_FILE_NAME
+        );
+
+        info_message("Last error", &text);
+    }
+
+    /// Handles the "Run Diagnostics" button, checks each stage of the
+    /// connection to the entered server in the background
+    fn handle_run_diagnostics(&self) {
+        // Only one run at a time
+        if self.diagnostics_task.borrow().is_some() {
+            return;
+        }
+
+        let target = servers::normalize_target(&self.target_url_input.text());
+        if target.is_empty() {
+            error_message(
+                "Run diagnostics",
+                "Enter the Connection URL of the server to check",
+            );
+            return;
+        }
+
+        self.diagnostics_button.set_enabled(false);
+        self.diagnostics_button.set_text("Running...");
+
+        let sender = self.diagnostics_notice.sender();
+        let http_client = self.http_client.clone();
+        let options = ConnectOptions::from_config(&self.config.borrow());
+
+        let task = tokio::spawn(async move {
+            let checks = run_connection_checks(http_client, target, options).await;
+            sender.notice();
+            checks
+        });
+
+        *self.diagnostics_task.borrow_mut() = Some(task);
+    }
+
+    /// Handles the connection checks completing, shows the checklist
+    fn handle_diagnostics_notice(&self) {
+        let result = self
+            .diagnostics_task
+            .borrow_mut()
+            .take()
+            .and_then(FutureExt::now_or_never);
+
+        self.diagnostics_button.set_enabled(true);
+        self.diagnostics_button.set_text("Run Diagnostics");
+
+        let Some(Ok(checks)) = result else {
+            error_message("Diagnostics", "The diagnostics stopped unexpectedly");
+            return;
+        };
+
+        let checklist: Vec<String> = checks.iter().map(ToString::to_string).collect();
+        let text = format!(
+            "{}\n\nThe first failing check shows where the connection is breaking",
+            checklist.join("\n")
+        );
+
+        info_message("Diagnostics", &text);
+    }
+
+    /// Handles the remember checkbox being toggled, stores the preference.
+    /// The saved connection URL is left as is, only later connections
+    /// stop being saved when no longer remembering
+    fn handle_remember_changed(&self) {
+        let config = &mut *self.config.borrow_mut();
+        config.remember = self.remember_checkbox.check_state() == CheckBoxState::Checked;
+
+        queue_config_write(config.clone());
+    }
+
+    /// Shows the first run explanation if it hasn't been shown before,
+    /// the explanation is only shown once
+    fn show_tutorial(&self) {
+        if self.config.borrow().seen_tutorial {
+            return;
+        }
+
+        info_message("Welcome to Pocket Relay", TUTORIAL_TEXT);
+
+        let config = &mut *self.config.borrow_mut();
+        config.seen_tutorial = true;
+        queue_config_write(config.clone());
+    }
+
+    /// Handles the "Set Min Version" button, validates and saves the
+    /// minimum server version override. Clearing the input removes the
+    /// override. Lowering the version requires confirmation
+    fn handle_set_min_version(&self) {
+        let value = self.min_version_input.text();
+        let value = value.trim();
+
+        let current = servers::effective_min_server_version(&self.config.borrow());
+
+        let min_version = if value.is_empty() {
+            None
+        } else {
+            let Some(version) = servers::parse_min_server_version(value) else {
+                error_message(
+                    "Invalid minimum server version",
+                    &format!(
+                        "\"{}\" is not a valid version, versions must be in the \
+                        MAJOR.MINOR.PATCH format (e.g. 0.6.0)",
+                        value
+                    ),
+                );
+                return;
+            };
+
+            if version < MIN_SERVER_VERSION {
+                error_message(
+                    "Invalid minimum server version",
+                    &format!(
+                        "This version of the client requires servers to be at least \
+                        v{}, the minimum server version can't be set lower",
+                        MIN_SERVER_VERSION
+                    ),
+                );
+                return;
+            }
+
+            Some(version)
+        };
+
+        let effective = min_version.clone().unwrap_or(MIN_SERVER_VERSION);
+        if effective < current
+            && !confirm_message(
+                "Lower minimum server version",
+                &format!(
+                    "Lowering the minimum server version from v{} to v{} allows connecting \
+                    to older servers that may be incompatible with this client, this can \
+                    cause errors or missing features in game.\n\nAre you sure?",
+                    current, effective
+                ),
+            )
+        {
+            self.update_min_version_input();
+            return;
+        }
+
+        let config = &mut *self.config.borrow_mut();
+        config.min_server_version = min_version.map(|version| version.to_string());
+        queue_config_write(config.clone());
+
+        // Cached lookups may be for servers that no longer meet the minimum
+        servers::clear_lookup_cache();
+
+        debug!("Minimum server version set to v{}", effective);
+    }
+
+    /// Updates the minimum server version input from the current config
+    fn update_min_version_input(&self) {
+        let config = self.config.borrow();
+        self.min_version_input
+            .set_text(config.min_server_version.as_deref().unwrap_or_default());
+    }
+
+    /// Handles the "Open Config Folder" button, opens the folder containing
+    /// the config file in Explorer so the config can be edited and reloaded
+    fn handle_open_config_folder(&self) {
+        let path = active_config_path();
+        let Some(folder) = path.parent() else {
+            return;
+        };
+
+        if let Err(err) = open_folder(folder) {
+            error!("Failed to open config folder: {}", err);
+            error_message("Failed to open config folder", &err);
+        }
+    }
+
+    /// Handles the "Reload Config" button, reads the config file again and
+    /// applies the settings that can change while the game is running.
+    /// Settings that are only used at startup are reported to the user
+    fn handle_reload_config(&self) {
+        let Some(config) = read_config_file() else {
+            error_message(
+                "Failed to reload config",
+                "The config file could not be loaded",
+            );
+            return;
+        };
+
+        // Settings that are only applied when the plugin starts
+        let restart_required = {
+            let current = self.config.borrow();
+            let mut changed = Vec::new();
+
+            if current.game_fingerprint != config.game_fingerprint {
+                changed.push("game_fingerprint");
+            }
+            if current.redirect_address != config.redirect_address {
+                changed.push("redirect_address");
+            }
+            if current.start_minimized != config.start_minimized {
+                changed.push("start_minimized");
+            }
+            if current.resource_pack != config.resource_pack {
+                changed.push("resource_pack");
+            }
+            if current.trace_log != config.trace_log {
+                changed.push("trace_log");
+            }
+            if current.event_file != config.event_file {
+                changed.push("event_file");
+            }
+
+            changed
+        };
+
+        set_trace_events(config.trace_events);
+
+        self.remember_checkbox.set_check_state(if config.remember {
+            CheckBoxState::Checked
+        } else {
+            CheckBoxState::Unchecked
+        });
+
+        if config.remember && !has_server_tasks() {
+            self.target_url_input.set_text(&config.connection_url);
+        }
+
+        *self.config.borrow_mut() = config;
+        self.update_recent_servers();
+        self.update_min_version_input();
+
+        if restart_required.is_empty() {
+            info_message("Config reloaded", "The config file was reloaded");
+        } else {
+            info_message(
+                "Config reloaded",
+                &format!(
+                    "The config file was reloaded, the following settings will \
+                    only apply after restarting the game:\n\n{}",
+                    restart_required.join("\n")
+                ),
+            );
+        }
+    }
+
+    /// Shows a file dialog for choosing a settings file, provides the
+    /// chosen path or [None] if the dialog was cancelled
+    ///
+    /// ## Arguments
+    /// * `title`  - The title of the dialog
+    /// * `action` - Whether the dialog is for opening or saving
+    fn choose_settings_file(&self, title: &str, action: FileDialogAction) -> Option<PathBuf> {
+        let mut dialog = FileDialog::default();
+        if let Err(err) = FileDialog::builder()
+            .title(title)
+            .action(action)
+            .filters("Settings(*.json)|Any(*.*)")
+            .build(&mut dialog)
+        {
+            error_message("Failed to open file dialog", &err.to_string());
+            return None;
+        }
+
+        if !dialog.run(Some(&self.window)) {
+            return None;
+        }
+
+        dialog.get_selected_item().ok().map(PathBuf::from)
+    }
+
+    /// Handles the "Export Settings" button, writes the shareable
+    /// settings to a file chosen by the user
+    fn handle_export_settings(&self) {
+        let Some(mut path) = self.choose_settings_file("Export settings", FileDialogAction::Save)
+        else {
+            return;
+        };
+
+        if path.extension().is_none() {
+            path.set_extension("json");
+        }
+
+        let config = self.config.borrow().to_shareable();
+        let result = serde_json::to_vec_pretty(&config)
+            .map_err(|err| err.to_string())
+            .and_then(|bytes| std::fs::write(&path, bytes).map_err(|err| err.to_string()));
+
+        match result {
+            Ok(()) => info_message(
+                "Settings exported",
+                &format!("Settings were exported to {}", path.display()),
+            ),
+            Err(err) => error_message("Failed to export settings", &err),
+        }
+    }
+
+    /// Handles the "Import Settings" button, reads settings from a file
+    /// chosen by the user and applies the server connection settings from
+    /// it, settings specific to this machine are kept
+    fn handle_import_settings(&self) {
+        let Some(path) = self.choose_settings_file("Import settings", FileDialogAction::Open)
+        else {
+            return;
+        };
+
+        let result = std::fs::read(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|bytes| {
+                serde_json::from_slice::<ClientConfig>(&bytes).map_err(|err| err.to_string())
+            });
+
+        let imported = match result {
+            Ok(value) => value,
+            Err(err) => {
+                error_message(
+                    "Failed to import settings",
+                    &format!("The file is not a valid settings file: {}", err),
+                );
+                return;
+            }
+        };
+
+        {
+            let config = &mut *self.config.borrow_mut();
+            config.merge_shared(imported);
+
+            set_trace_events(config.trace_events);
+            self.remember_checkbox.set_check_state(if config.remember {
+                CheckBoxState::Checked
+            } else {
+                CheckBoxState::Unchecked
+            });
+
+            if !has_server_tasks() {
+                self.target_url_input.set_text(&config.connection_url);
+            }
+
+            queue_config_write(config.clone());
+        }
+
+        self.update_recent_servers();
+        self.update_min_version_input();
+        info_message("Settings imported", "The imported settings have been saved");
+    }
+
+    /// Handles a recent server being selected from the dropdown, fills
+    /// the connection URL input with the selected server
+    fn handle_recent_selected(&self) {
+        let Some(index) = self.recent_select.selection() else {
+            return;
+        };
+
+        let config = self.config.borrow();
+        if let Some((_, target)) = dropdown_entries(&config).into_iter().nth(index) {
+            self.target_url_input.set_text(&target);
+        }
+    }
+
+    /// Updates the recent servers dropdown from the current config, when
+    /// the config restricts the allowed hosts the dropdown lists the allowed
+    /// hosts and the connection URL can't be edited
+    fn update_recent_servers(&self) {
+        let config = self.config.borrow();
+
+        // Free text entry is replaced by the allowed hosts when set
+        self.target_url_input
+            .set_readonly(!config.allowed_hosts.is_empty());
+
+        let labels = dropdown_entries(&config)
+            .into_iter()
+            .map(|(label, _)| label)
+            .collect();
+
+        self.recent_select.set_collection(labels);
+    }
+
+    /// Handles the update state changing, connecting is disabled and
+    /// a message is shown while an update is being downloaded
+    fn handle_update_notice(&self) {
+        let updating = update::is_updating();
+
+        self.set_button.set_enabled(!updating);
+        self.connect_label.set_text(if updating {
+            UPDATING_LABEL_TEXT
+        } else {
+            CONNECT_LABEL_TEXT
+        });
+    }
+
     /// Handles the connection complete notice updating the UI
     /// with the new connection state from the task result
     fn handle_connect_notice(&self) {
@@ -128,51 +1087,85 @@ impl App {
             .borrow_mut()
             .take()
             // Flatten on the join result
-            .and_then(FutureExt::now_or_never)
-            // Flatten join failure errors (Out of our control)
-            .and_then(Result::ok);
+            .and_then(FutureExt::now_or_never);
 
         // Ensure theres actually a result to use
         let Some(result) = result else { return };
 
-        let mut lookup = match result {
+        // Join failure errors are out of our control but the connection
+        // attempt is over
+        let Ok(result) = result else {
+            set_connection_state(ConnectionState::Disconnected);
+            self.connection_label.set_text("Failed to connect");
+            self.set_button.set_text("Connect");
+            self.start_cooldown();
+            return;
+        };
+
+        let ConnectedServer { lookup, banner } = match result {
             Ok(value) => value,
             Err(err) => {
+                set_connection_state(ConnectionState::Disconnected);
                 self.connection_label.set_text("Failed to connect");
+                self.set_button.set_text("Connect");
+                self.start_cooldown();
+
+                // Connecting from the tray needs the window to try again
+                self.window.set_visible(true);
+
+                record_error(ErrorCategory::Connection, err.to_string());
                 error_message("Failed to connect", &err.to_string());
                 return;
             }
         };
 
-        let ctx = Arc::new(ClientContext {
-            http_client: self.http_client.clone(),
-            base_url: lookup.url.clone(),
-            association: lookup.association.take(),
-            tunnel_port: lookup.tunnel_port,
-        });
+        set_connection_state(ConnectionState::Connected);
 
-        // Start the servers
-        start_all_servers(ctx);
+        {
+            let config = &mut *self.config.borrow_mut();
+            let connection_url = lookup.url.to_string();
 
-        let remember = self.remember_checkbox.check_state() == CheckBoxState::Checked;
+            config.add_recent_server(connection_url.clone(), lookup.version.to_string());
 
-        // Save the connection URL
-        if remember {
-            let connection_url = lookup.url.to_string();
-            write_config_file(ClientConfig { connection_url });
+            // Save the connection URL
+            if config.remember {
+                config.connection_url = connection_url;
+            }
+
+            queue_config_write(config.clone());
         }
 
-        let text = format!(
-            "Connected: {} {} version v{}",
-            lookup.url.scheme(),
-            lookup.url.authority(),
-            lookup.version
-        );
-        self.connection_label.set_text(&text);
+        self.update_recent_servers();
+
+        self.set_connected_text(&lookup);
         self.set_button.set_text("Disconnect");
+        self.start_cooldown();
+
+        // Start measuring the latency to the server
+        servers::start_latency_probe(
+            self.http_client.clone(),
+            lookup.url.clone(),
+            self.latency_notice.sender(),
+        );
+
+        // Watch for the server being updated while connected
+        let config = &*self.config.borrow();
+        if let Some(minutes) = config.version_check_interval.filter(|minutes| *minutes > 0) {
+            servers::start_version_check(
+                self.http_client.clone(),
+                lookup.url.clone(),
+                lookup.version.clone(),
+                servers::effective_min_server_version(config),
+                Duration::from_secs(minutes * 60),
+            );
+        }
 
         // Resume game threads
         resume_all_threads();
+
+        if let Some(message) = banner.message() {
+            info_message("Server message", &message);
+        }
     }
 }
 
@@ -183,16 +1176,37 @@ impl App {
 /// * `client` - The HTTP client to use
 pub fn init(config: Option<ClientConfig>, client: Client) {
     // Create tokio async runtime
-    let runtime = tokio::runtime::Builder::new_multi_thread()
+    let runtime = match tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
-        .expect("Failed building tokio runtime");
+    {
+        Ok(value) => value,
+        Err(err) => {
+            error!("Failed building tokio runtime: {}", err);
+            error_message(
+                "Failed to start Pocket Relay",
+                &format!(
+                    "The plugin failed to start its background runtime, you will only be able \
+                    to play offline. Restarting the game may fix this, if it keeps happening \
+                    try removing the plugin.\n\nError: {}",
+                    err
+                ),
+            );
+
+            // Let the game continue without the plugin
+            resume_all_threads();
+            return;
+        }
+    };
 
     // Enter the tokio runtime
     let _enter = runtime.enter();
 
-    // Spawn the updating task
-    tokio::spawn(update::update(client.clone()));
+    // Allow the UI to be stopped when the plugin is unloaded
+    shutdown::set_ui_thread();
+
+    // Allow external launchers to use the runtime
+    launcher::init(runtime.handle().clone(), client.clone());
 
     // Initialize nwg
     nwg_init().expect("Failed to initialize native UI");
@@ -200,31 +1214,264 @@ pub fn init(config: Option<ClientConfig>, client: Client) {
     // Set the default font family
     Font::set_global_family("Segoe UI").expect("Failed to set default font");
 
+    let config = config.unwrap_or_default();
+    let remember = config.remember;
+    let launch_args = launch_args();
+    let connection_url = launch_args
+        .connection_url
+        .as_ref()
+        .unwrap_or(&config.connection_url);
+    let target = if ConnectOptions::from_config(&config).is_host_allowed(connection_url) {
+        connection_url.clone()
+    } else {
+        // Saved URL isn't allowed, use the first allowed host instead
+        config.allowed_hosts.first().cloned().unwrap_or_default()
+    };
+    let start_minimized = config.start_minimized || launch_args.headless;
+    let auto_update = config.auto_update;
+    let offline_hotkey = config.offline_hotkey.clone();
+    let icon = load_icon(config.resource_pack.as_deref());
+
     // Build the app UI
     let app = App::build_ui(App {
-        http_client: client,
+        icon,
+        http_client: client.clone(),
+        config: RefCell::new(config),
         ..Default::default()
     })
     .expect("Failed to build native UI");
 
-    let (target, remember) = config
-        .map(|value| (value.connection_url, true))
-        .unwrap_or_default();
+    // Launcher requests update the UI through the notice
+    launcher::set_ui_notice(app.launcher_notice.sender());
+
+    // Spawn the updating task
+    if auto_update {
+        tokio::spawn(update::update(client, app.update_notice.sender(), false));
+    } else {
+        debug!("Automatic updates are disabled");
+    }
+
+    if let Some(hotkey) = offline_hotkey {
+        match hotkey.parse::<Hotkey>() {
+            Ok(value) => {
+                debug!("Offline mode hotkey: {}", hotkey);
+                start_hotkey_listener(value, app.offline_notice.sender());
+            }
+            Err(err) => error!("Invalid offline hotkey {:?}: {}", hotkey, err),
+        }
+    }
 
     app.target_url_input.set_text(&target);
+    app.update_recent_servers();
+    app.update_min_version_input();
 
     if remember {
         app.remember_checkbox
             .set_check_state(CheckBoxState::Checked);
     }
 
+    // Warn the user if the game will not be redirected
+    let hook_status = hook_status();
+    if hook_status.has_critical_failure() {
+        if let Some(summary) = hook_status.failure_summary() {
+            error_message(
+                "Failed to apply hooks",
+                &format!(
+                    "Some of the game hooks failed to apply, the following features \
+                    are unavailable:\n\n{}",
+                    summary
+                ),
+            );
+        }
+    }
+
+    // Connect silently from the tray when a connection URL is saved
+    if start_minimized && !target.is_empty() {
+        app.window.set_visible(false);
+        app.handle_set();
+    } else if launch_args.connect && !target.is_empty() {
+        // Launcher requested connecting on startup
+        app.handle_set();
+    } else {
+        app.show_tutorial();
+    }
+
     dispatch_thread_events();
 
+    // Write any config changes that are still pending
+    flush_config_write();
+
     // Resume the game threads if we close the UI
     resume_all_threads();
 
-    let shutdown_signal = tokio::signal::ctrl_c();
-    let _ = runtime.block_on(shutdown_signal);
+    // Keep the runtime alive until the game closes or the plugin is unloaded
+    runtime.block_on(async {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = shutdown::wait_for_shutdown() => {}
+        }
+    });
+
+    // Server tasks are aborted first so nothing is relaying during unload
+    servers::stop_all_servers();
+
+    // Remaining tasks are given a bounded time to stop
+    drop(_enter);
+    runtime.shutdown_timeout(shutdown::SHUTDOWN_TIMEOUT);
+}
+
+/// Opens the folder at `path` in Explorer
+///
+/// ## Arguments
+/// * `path` - The folder to open
+fn open_folder(path: &Path) -> Result<(), String> {
+    let operation: Vec<u16> = OsStr::new("open").encode_wide().chain(once(0)).collect();
+    let file: Vec<u16> = path.as_os_str().encode_wide().chain(once(0)).collect();
+
+    let result = unsafe {
+        ShellExecuteW(
+            0,
+            operation.as_ptr(),
+            file.as_ptr(),
+            null(),
+            null(),
+            SW_SHOWNORMAL,
+        )
+    };
+
+    // Values above 32 indicate success
+    if result > 32 {
+        Ok(())
+    } else {
+        Err(format!(
+            "Unable to open {} (error code {})",
+            path.display(),
+            result
+        ))
+    }
+}
+
+/// Loads the window icon, the resource pack icon is used when available
+/// otherwise the embedded icon is used. An embedded icon that fails to
+/// load falls back to the system icon rather than preventing the UI from
+/// starting
+///
+/// ## Arguments
+/// * `resource_pack` - Optional path to the resource pack directory
+fn load_icon(resource_pack: Option<&str>) -> Icon {
+    if let Some(icon) = resource_pack.and_then(load_resource_pack_icon) {
+        return icon;
+    }
+
+    let mut icon = Icon::default();
+    match Icon::builder()
+        .source_bin(Some(ICON_BYTES))
+        .build(&mut icon)
+    {
+        Ok(_) => return icon,
+        Err(err) => error!("Failed to load embedded icon: {}", err),
+    }
+
+    if let Err(err) = Icon::builder()
+        .source_system(Some(OemIcon::WinLogo))
+        .build(&mut icon)
+    {
+        error!("Failed to load system icon: {}", err);
+    }
+
+    icon
+}
+
+/// Loads the icon from the resource pack directory at `path`, provides
+/// [None] when the pack doesn't contain an icon or the icon couldn't be
+/// loaded so the embedded icon is used instead
+///
+/// ## Arguments
+/// * `path` - Path to the resource pack directory
+fn load_resource_pack_icon(path: &str) -> Option<Icon> {
+    let icon_path = Path::new(path).join(RESOURCE_PACK_ICON);
+    if !icon_path.exists() {
+        debug!("Resource pack has no icon: {}", icon_path.display());
+        return None;
+    }
+
+    let bytes = match std::fs::read(&icon_path) {
+        Ok(value) => value,
+        Err(err) => {
+            warn!("Failed to read resource pack icon: {}", err);
+            return None;
+        }
+    };
+
+    // Building the icon decodes it, invalid icons fail here
+    let mut icon = Icon::default();
+    if let Err(err) = Icon::builder().source_bin(Some(&bytes)).build(&mut icon) {
+        warn!("Resource pack icon is not a valid icon: {}", err);
+        return None;
+    }
+
+    debug!("Loaded resource pack icon: {}", icon_path.display());
+    Some(icon)
+}
+
+/// Creates the label and connection target for each entry of the recent
+/// servers dropdown. Only the allowed hosts are listed when the config
+/// restricts them, otherwise the connection profiles are listed followed
+/// by the recent servers
+///
+/// ## Arguments
+/// * `config` - The client config
+fn dropdown_entries(config: &ClientConfig) -> Vec<(String, String)> {
+    if !config.allowed_hosts.is_empty() {
+        return config
+            .allowed_hosts
+            .iter()
+            .map(|host| (host.clone(), host.clone()))
+            .collect();
+    }
+
+    let now = unix_timestamp();
+    let profiles = config
+        .profiles
+        .iter()
+        .map(|profile| (format!("{} (Profile)", profile.name), profile.name.clone()));
+    let recent = config
+        .recent_servers
+        .iter()
+        .map(|server| (recent_server_label(server, now), server.url.clone()));
+
+    profiles.chain(recent).collect()
+}
+
+/// Formats an elapsed duration as a human readable relative time
+///
+/// ## Arguments
+/// * `elapsed` - The elapsed time in seconds
+fn format_elapsed(elapsed: u64) -> String {
+    const MINUTE: u64 = 60;
+    const HOUR: u64 = MINUTE * 60;
+    const DAY: u64 = HOUR * 24;
+
+    if elapsed < MINUTE {
+        "just now".to_string()
+    } else if elapsed < HOUR {
+        format!("{} minutes ago", elapsed / MINUTE)
+    } else if elapsed < DAY {
+        format!("{} hours ago", elapsed / HOUR)
+    } else {
+        format!("{} days ago", elapsed / DAY)
+    }
+}
+
+/// Creates the dropdown label for a recent server
+///
+/// ## Arguments
+/// * `server` - The recent server
+/// * `now`    - The current unix timestamp in seconds
+fn recent_server_label(server: &RecentServer, now: u64) -> String {
+    let last_connected = format_elapsed(now.saturating_sub(server.last_connected));
+
+    format!("{} (v{}, {})", server.url, server.version, last_connected)
 }
 
 /// Shows a confirmation message to the user returning
@@ -271,3 +1518,44 @@ pub fn error_message(title: &str, text: &str) {
         icons: MessageIcons::Error,
     });
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Cooldown, CONNECT_COOLDOWN};
+    use std::time::{Duration, Instant};
+
+    /// Cooldown ends after [CONNECT_COOLDOWN]
+    #[test]
+    fn test_cooldown() {
+        let now = Instant::now();
+        let mut cooldown = Cooldown::default();
+        assert_eq!(cooldown.remaining(now), None);
+
+        assert_eq!(cooldown.start(now), CONNECT_COOLDOWN);
+        assert_eq!(cooldown.remaining(now), Some(CONNECT_COOLDOWN));
+        assert_eq!(cooldown.remaining(now + CONNECT_COOLDOWN), None);
+    }
+
+    /// Connecting then disconnecting straight away must not re-enable the
+    /// button when the first cooldown ends
+    #[test]
+    fn test_cooldown_back_to_back() {
+        let now = Instant::now();
+        let step = Duration::from_millis(100);
+        let mut cooldown = Cooldown::default();
+
+        // Connect
+        cooldown.start(now);
+
+        // Disconnect shortly after
+        let disconnect = now + step;
+        assert_eq!(cooldown.start(disconnect), CONNECT_COOLDOWN);
+
+        // First cooldown ending keeps the button disabled
+        assert_eq!(cooldown.remaining(now + CONNECT_COOLDOWN), Some(step));
+        assert_eq!(cooldown.remaining(disconnect + CONNECT_COOLDOWN), None);
+
+        // Starting an earlier cooldown doesn't shorten the current one
+        assert_eq!(cooldown.start(now), CONNECT_COOLDOWN + step);
+    }
+}
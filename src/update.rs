@@ -2,21 +2,62 @@
 
 use crate::{
     core::{
-        reqwest,
-        update::{download_latest_release, get_latest_release},
+        reqwest::{self, header, StatusCode},
+        update::{get_latest_release, GitHubReleaseAsset},
         Version,
     },
+    diagnostics::{record_error, ErrorCategory},
+    event_file::{write_event, Event},
+    event_log::{report_event, EventKind},
     ui::{confirm_message, error_message, info_message},
     APP_VERSION,
 };
-use log::{debug, error};
-use std::{env::current_exe, path::PathBuf, process::exit};
+use log::{debug, error, warn};
+use native_windows_gui::NoticeSender;
+use std::{
+    env::current_exe,
+    fmt::Display,
+    path::{Path, PathBuf},
+    process::exit,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+};
 
 /// The GitHub repository to use for releases
 pub const GITHUB_REPOSITORY: &str = "PocketRelay/PocketRelayClientPlugin";
 /// GitHub asset name for the plugin file
 pub const ASSET_NAME: &str = "pocket-relay-plugin.asi";
 
+/// Offset within the DOS header to the PE header offset
+const PE_HEADER_OFFSET: usize = 0x3C;
+/// Machine type for x86 (32bit) binaries
+const IMAGE_FILE_MACHINE_I386: u16 = 0x014C;
+/// Characteristics flag set for DLL binaries
+const IMAGE_FILE_DLL: u16 = 0x2000;
+
+/// Whether an update is currently being downloaded and applied
+static UPDATING: AtomicBool = AtomicBool::new(false);
+
+/// Returns whether an update is currently in progress
+pub fn is_updating() -> bool {
+    UPDATING.load(Ordering::Acquire)
+}
+
+/// Updates the current update state and notifies the UI
+/// that the state has changed
+///
+/// ## Arguments
+/// * `updating` - Whether an update is in progress
+/// * `notice`   - Notice sender to wake the UI
+fn set_updating(updating: bool, notice: NoticeSender) {
+    UPDATING.store(updating, Ordering::Release);
+    notice.notice();
+}
+
 /// Paths used by the updater
 pub struct UpdatePaths {
     /// Path to the .asi plugin file
@@ -25,6 +66,9 @@ pub struct UpdatePaths {
     pub tmp_download: PathBuf,
     /// Temporary path for moving the old plugin before swapping
     pub tmp_old: PathBuf,
+    /// Temporary path storing the release version of the download at
+    /// `tmp_download` so partial downloads are only resumed for that version
+    pub tmp_version: PathBuf,
 }
 
 impl Default for UpdatePaths {
@@ -40,26 +84,62 @@ impl Default for UpdatePaths {
             plugin: asi_path.join("pocket-relay-plugin.asi"),
             tmp_download: asi_path.join("pocket-relay-plugin.asi.tmp-download"),
             tmp_old: asi_path.join("pocket-relay-plugin.asi.tmp-old"),
+            tmp_version: asi_path.join("pocket-relay-plugin.asi.tmp-version"),
         }
     }
 }
 
 impl UpdatePaths {
-    // Removes the temporary paths if they exist
-    pub async fn remove_tmp_paths(&self) -> std::io::Result<()> {
+    /// Removes the old plugin left behind by a completed swap if it exists
+    pub async fn remove_tmp_old(&self) -> std::io::Result<()> {
         if self.tmp_old.exists() {
             tokio::fs::remove_file(&self.tmp_old).await?;
         }
 
+        Ok(())
+    }
+
+    /// Removes the downloaded plugin and its version if they exist
+    pub async fn remove_tmp_download(&self) -> std::io::Result<()> {
         if self.tmp_download.exists() {
             tokio::fs::remove_file(&self.tmp_download).await?;
         }
 
+        if self.tmp_version.exists() {
+            tokio::fs::remove_file(&self.tmp_version).await?;
+        }
+
         Ok(())
     }
 
+    /// Prepares `tmp_download` for downloading the `version` release, a
+    /// partial download is only kept if it was for the same version
+    ///
+    /// ## Arguments
+    /// * `version` - The version of the release being downloaded
+    pub async fn prepare_download(&self, version: &Version) -> std::io::Result<()> {
+        let version = version.to_string();
+
+        if self.tmp_download.exists() {
+            let partial_version = tokio::fs::read_to_string(&self.tmp_version)
+                .await
+                .unwrap_or_default();
+
+            if partial_version == version {
+                debug!("Keeping partial update download for v{}", version);
+                return Ok(());
+            }
+
+            debug!("Removing partial update download for a different version");
+            self.remove_tmp_download().await?;
+        }
+
+        tokio::fs::write(&self.tmp_version, version).await
+    }
+
     /// Moves the `plugin` file to `tmp_old` and moves the downloaded
-    /// file from `tmp_download` to `plugin`
+    /// file from `tmp_download` to `plugin`, the `plugin` file is moved
+    /// back if the downloaded file couldn't be moved
     pub async fn swap_plugin_files(&self) -> std::io::Result<()> {
         debug!("Swapping plugin files with update");
 
@@ -67,21 +147,230 @@ impl UpdatePaths {
         tokio::fs::rename(&self.plugin, &self.tmp_old).await?;
 
         // Move the downloaded plugin to the `plugin` path
-        tokio::fs::rename(&self.tmp_download, &self.plugin).await?;
+        if let Err(err) = tokio::fs::rename(&self.tmp_download, &self.plugin).await {
+            // Restore the current plugin so the game can still load it
+            if let Err(err) = tokio::fs::rename(&self.tmp_old, &self.plugin).await {
+                error!("Failed to restore plugin file: {}", err);
+            }
+
+            return Err(err);
+        }
 
         Ok(())
     }
 }
 
+/// Maximum number of attempts at downloading the update, interrupted
+/// downloads are resumed by the next attempt
+const MAX_DOWNLOAD_ATTEMPTS: u32 = 5;
+/// Delay between download attempts
+const DOWNLOAD_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Errors that can occur while downloading an update
+#[derive(Debug)]
+pub enum DownloadError {
+    /// Failed to make the request or read the response
+    Request(reqwest::Error),
+    /// Server responded with an unexpected status
+    Status(StatusCode),
+    /// Failed to write the downloaded file
+    Io(std::io::Error),
+    /// Downloaded file size didn't match the size from the server
+    SizeMismatch { expected: u64, actual: u64 },
+}
+
+impl Display for DownloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DownloadError::Request(err) => err.fmt(f),
+            DownloadError::Status(status) => write!(f, "Unexpected response status: {}", status),
+            DownloadError::Io(err) => write!(f, "Failed to save download: {}", err),
+            DownloadError::SizeMismatch { expected, actual } => write!(
+                f,
+                "Downloaded file is {} bytes but the server reported {} bytes",
+                actual, expected
+            ),
+        }
+    }
+}
+
+impl From<reqwest::Error> for DownloadError {
+    fn from(value: reqwest::Error) -> Self {
+        DownloadError::Request(value)
+    }
+}
+
+impl From<std::io::Error> for DownloadError {
+    fn from(value: std::io::Error) -> Self {
+        DownloadError::Io(value)
+    }
+}
+
+/// Downloads the release `asset` to the `path`, interrupted downloads are
+/// retried resuming from the partially downloaded file using HTTP range
+/// requests. Servers that don't support ranges restart the download
+///
+/// ## Arguments
+/// * `http_client` - The HTTP client to download with
+/// * `asset`       - The release asset to download
+/// * `path`        - Path to download the file to
+pub async fn download_release(
+    http_client: &reqwest::Client,
+    asset: &GitHubReleaseAsset,
+    path: &Path,
+) -> Result<Vec<u8>, DownloadError> {
+    let mut attempt = 1;
+
+    loop {
+        let err = match download_attempt(http_client, asset, path).await {
+            Ok(()) => return Ok(tokio::fs::read(path).await?),
+            Err(err) => err,
+        };
+
+        // Size mismatches can't be fixed by resuming
+        if let DownloadError::SizeMismatch { .. } = &err {
+            tokio::fs::remove_file(path).await?;
+        }
+
+        if attempt >= MAX_DOWNLOAD_ATTEMPTS {
+            return Err(err);
+        }
+
+        warn!(
+            "Update download attempt {} failed, retrying: {}",
+            attempt, err
+        );
+
+        attempt += 1;
+        tokio::time::sleep(DOWNLOAD_RETRY_DELAY).await;
+    }
+}
+
+/// Makes a single attempt at downloading the `asset` to `path` resuming
+/// from the existing file at `path` if one is present
+///
+/// ## Arguments
+/// * `http_client` - The HTTP client to download with
+/// * `asset`       - The release asset to download
+/// * `path`        - Path to download the file to
+async fn download_attempt(
+    http_client: &reqwest::Client,
+    asset: &GitHubReleaseAsset,
+    path: &Path,
+) -> Result<(), DownloadError> {
+    let existing = match tokio::fs::metadata(path).await {
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    };
+
+    let mut request = http_client.get(&asset.browser_download_url);
+    if existing > 0 {
+        debug!("Resuming update download from {} bytes", existing);
+        request = request.header(header::RANGE, format!("bytes={}-", existing));
+    }
+
+    let mut response = request.send().await?;
+
+    let (mut file, expected) = match response.status() {
+        // Server is resuming the download
+        StatusCode::PARTIAL_CONTENT => {
+            let total = response
+                .headers()
+                .get(header::CONTENT_RANGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.rsplit_once('/'))
+                .and_then(|(_, total)| total.parse::<u64>().ok());
+
+            let file = OpenOptions::new().append(true).open(path).await?;
+            (file, total)
+        }
+        // Server doesn't support ranges, the download starts again
+        StatusCode::OK => {
+            if existing > 0 {
+                debug!("Server doesn't support resuming, restarting update download");
+            }
+
+            let total = response.content_length();
+            let file = File::create(path).await?;
+            (file, total)
+        }
+        // Partial file isn't valid for the current asset
+        StatusCode::RANGE_NOT_SATISFIABLE => {
+            tokio::fs::remove_file(path).await?;
+            return Err(DownloadError::Status(StatusCode::RANGE_NOT_SATISFIABLE));
+        }
+        status => return Err(DownloadError::Status(status)),
+    };
+
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk).await?;
+    }
+
+    file.flush().await?;
+
+    let actual = tokio::fs::metadata(path).await?.len();
+    match expected {
+        Some(expected) if expected != actual => {
+            Err(DownloadError::SizeMismatch { expected, actual })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks that the provided `bytes` are a 32bit Windows DLL by checking
+/// the DOS and PE headers, the .asi plugin is just a renamed DLL
+///
+/// ## Arguments
+/// * `bytes` - The downloaded plugin bytes
+pub fn is_valid_plugin(bytes: &[u8]) -> bool {
+    // Read a little endian u16 at the provided offset
+    let read_u16 = |offset: usize| -> Option<u16> {
+        let value = bytes.get(offset..offset + 2)?;
+        Some(u16::from_le_bytes([value[0], value[1]]))
+    };
+
+    // Check the DOS header magic
+    if !bytes.starts_with(b"MZ") {
+        return false;
+    }
+
+    // Find the PE header position
+    let Some(pe_offset) = bytes
+        .get(PE_HEADER_OFFSET..PE_HEADER_OFFSET + 4)
+        .map(|value| u32::from_le_bytes([value[0], value[1], value[2], value[3]]) as usize)
+    else {
+        return false;
+    };
+
+    // Check the PE header signature
+    if bytes.get(pe_offset..pe_offset + 4) != Some(b"PE\0\0") {
+        return false;
+    }
+
+    // File header is directly after the signature
+    let file_header = pe_offset + 4;
+
+    let machine = read_u16(file_header);
+    let characteristics = read_u16(file_header + 18);
+
+    matches!(
+        (machine, characteristics),
+        (Some(IMAGE_FILE_MACHINE_I386), Some(characteristics))
+            if characteristics & IMAGE_FILE_DLL != 0
+    )
+}
+
 /// Handles updating the client plugin the latest version from GitHub
 ///
 /// ## Arguments
 /// * `http_client` - The HTTP client to use when requesting and downloading the update
-pub async fn update(http_client: reqwest::Client) {
+/// * `notice`      - Notice sender for informing the UI of update progress
+/// * `manual`      - Whether the user requested the check, reports when there is no update
+pub async fn update(http_client: reqwest::Client, notice: NoticeSender, manual: bool) {
     let paths = UpdatePaths::default();
 
-    // Remove temporary files if they exist
-    if let Err(err) = paths.remove_tmp_paths().await {
+    // The partial download is kept until it has been checked against the release
+    if let Err(err) = paths.remove_tmp_old().await {
         error!("Failed to remove temporary files: {}", err);
     }
 
@@ -91,6 +380,9 @@ pub async fn update(http_client: reqwest::Client) {
         Ok(value) => value,
         Err(err) => {
             error!("Failed to fetch latest release: {}", err);
+            if manual {
+                error_message("Failed to check for updates", &err.to_string());
+            }
             return;
         }
     };
@@ -118,6 +410,18 @@ pub async fn update(http_client: reqwest::Client) {
             debug!("Latest version is installed ({})", current_version);
         }
 
+        // Partial downloads can only be for older releases
+        if let Err(err) = paths.remove_tmp_download().await {
+            error!("Failed to remove temporary files: {}", err);
+        }
+
+        if manual {
+            info_message(
+                "No updates available",
+                &format!("You are using the latest version (v{})", current_version),
+            );
+        }
+
         return;
     }
 
@@ -145,31 +449,83 @@ pub async fn update(http_client: reqwest::Client) {
 
     debug!("Downloading release");
 
-    let bytes = match download_latest_release(&http_client, asset).await {
+    set_updating(true, notice);
+
+    if let Err(err) = paths.prepare_download(&latest_version).await {
+        error!("Failed to prepare update download: {}", err);
+    }
+
+    let bytes = match download_release(&http_client, asset, &paths.tmp_download).await {
         Ok(bytes) => bytes,
         Err(err) => {
+            set_updating(false, notice);
+            record_error(ErrorCategory::Update, err.to_string());
             error_message("Failed to download", &err.to_string());
 
-            // Delete partially downloaded file if present
-            if let Err(err) = paths.remove_tmp_paths().await {
-                error!("Failed to remove temporary files: {}", err);
-            }
-
+            // Partial download is kept so the next update can resume it
             return;
         }
     };
 
-    // Save the downloaded file to the tmp path
-    if let Err(err) = tokio::fs::write(&paths.tmp_download, bytes).await {
-        error_message("Failed to save downloaded update", &err.to_string());
+    // Ensure the download is actually a plugin and not an error page
+    if !is_valid_plugin(&bytes) {
+        set_updating(false, notice);
+        error!("Downloaded update is not a valid plugin binary");
+        record_error(
+            ErrorCategory::Update,
+            "Downloaded update is not a valid plugin binary",
+        );
+        error_message(
+            "Failed to update",
+            "The downloaded update was not a valid plugin file, your current plugin has been kept",
+        );
+
+        // Download can't be resumed into a valid plugin
+        if let Err(err) = paths.remove_tmp_download().await {
+            error!("Failed to remove temporary files: {}", err);
+        }
+
         return;
     }
 
     // Swap the plugin files with the new version
     if let Err(err) = paths.swap_plugin_files().await {
+        set_updating(false, notice);
         error!("Failed to swap plugin files: {}", err);
+        record_error(
+            ErrorCategory::Update,
+            format!("Failed to swap plugin files: {}", err),
+        );
+        error_message(
+            "Failed to update",
+            &format!(
+                "Failed to replace the plugin file, your current plugin has been kept: {}",
+                err
+            ),
+        );
+
+        // Completed download is kept for the next update, the old plugin
+        // at `tmp_old` may not have been restored so it isn't removed
+        return;
+    }
+
+    // Download has been moved into place, only its version remains
+    if let Err(err) = paths.remove_tmp_download().await {
+        error!("Failed to remove temporary files: {}", err);
     }
 
+    report_event(
+        EventKind::Information,
+        &format!(
+            "Plugin updated from v{} to v{}",
+            current_version, latest_version
+        ),
+    );
+    write_event(Event::Updated {
+        from: &current_version.to_string(),
+        to: &latest_version.to_string(),
+    });
+
     info_message(
         "Update successful",
         "The client has been updated, restart the game now to use the new version",